@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+const BINDINGS_FILE: &str = "bindings.ron";
+/// How far the left stick must be pushed in a direction to count as held
+pub const STICK_DEADZONE: f32 = 0.5;
+
+/// Something the player can do with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum GameAction {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateCW,
+    RotateCCW,
+    Rotate180,
+    Hold,
+    /// Start a game from the title screen, or continue after one ends
+    Confirm,
+    /// Pause the game, or resume it while paused
+    Pause,
+    ToggleMusic,
+    ToggleSound,
+}
+
+impl GameAction {
+    pub const ALL: [GameAction; 12] = [
+        GameAction::MoveLeft,
+        GameAction::MoveRight,
+        GameAction::SoftDrop,
+        GameAction::HardDrop,
+        GameAction::RotateCW,
+        GameAction::RotateCCW,
+        GameAction::Rotate180,
+        GameAction::Hold,
+        GameAction::Confirm,
+        GameAction::Pause,
+        GameAction::ToggleMusic,
+        GameAction::ToggleSound,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameAction::MoveLeft => "Move Left",
+            GameAction::MoveRight => "Move Right",
+            GameAction::SoftDrop => "Soft Drop",
+            GameAction::HardDrop => "Hard Drop",
+            GameAction::RotateCW => "Rotate CW",
+            GameAction::RotateCCW => "Rotate CCW",
+            GameAction::Rotate180 => "Rotate 180",
+            GameAction::Hold => "Hold",
+            GameAction::Confirm => "Confirm",
+            GameAction::Pause => "Pause",
+            GameAction::ToggleMusic => "Music",
+            GameAction::ToggleSound => "Sound",
+        }
+    }
+
+    /// Menu actions are never used during play, so they can share keys with
+    /// everything else.
+    fn is_menu(&self) -> bool {
+        matches!(self, GameAction::Confirm)
+    }
+
+    /// Gamepad buttons for the action. These can't be rebound.
+    fn buttons(&self) -> &'static [GamepadButton] {
+        match self {
+            GameAction::MoveLeft => &[GamepadButton::DPadLeft],
+            GameAction::MoveRight => &[GamepadButton::DPadRight],
+            GameAction::SoftDrop => &[GamepadButton::DPadDown],
+            GameAction::HardDrop => &[GamepadButton::DPadUp],
+            GameAction::RotateCW => &[GamepadButton::East],
+            GameAction::RotateCCW => &[GamepadButton::South],
+            GameAction::Rotate180 => &[GamepadButton::North],
+            GameAction::Hold => &[GamepadButton::LeftTrigger, GamepadButton::RightTrigger],
+            GameAction::Confirm => &[GamepadButton::Start, GamepadButton::South],
+            GameAction::Pause => &[GamepadButton::Start],
+            GameAction::ToggleMusic | GameAction::ToggleSound => &[],
+        }
+    }
+
+    /// The left stick direction that also counts as the action.
+    fn stick(&self) -> Option<Vec2> {
+        match self {
+            GameAction::MoveLeft => Some(Vec2::NEG_X),
+            GameAction::MoveRight => Some(Vec2::X),
+            GameAction::SoftDrop => Some(Vec2::NEG_Y),
+            _ => None,
+        }
+    }
+
+    fn default_keys(&self) -> Vec<KeyCode> {
+        match self {
+            GameAction::MoveLeft => vec![KeyCode::KeyA, KeyCode::ArrowLeft],
+            GameAction::MoveRight => vec![KeyCode::KeyD, KeyCode::ArrowRight],
+            GameAction::SoftDrop => vec![KeyCode::KeyS, KeyCode::ArrowDown],
+            GameAction::HardDrop => vec![KeyCode::Space],
+            GameAction::RotateCW => vec![KeyCode::KeyE, KeyCode::KeyX],
+            GameAction::RotateCCW => vec![KeyCode::KeyQ, KeyCode::KeyZ],
+            GameAction::Rotate180 => vec![KeyCode::KeyW],
+            GameAction::Hold => vec![KeyCode::KeyC, KeyCode::ShiftLeft, KeyCode::ShiftRight],
+            GameAction::Confirm => vec![KeyCode::Space],
+            GameAction::Pause => vec![KeyCode::Escape, KeyCode::KeyP],
+            GameAction::ToggleMusic => vec![KeyCode::KeyM],
+            GameAction::ToggleSound => vec![KeyCode::KeyN],
+        }
+    }
+}
+
+/// The keys bound to each action, saved whenever they change.
+#[derive(Resource, Serialize, Deserialize)]
+pub struct Bindings(BTreeMap<GameAction, Vec<KeyCode>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self(
+            GameAction::ALL
+                .iter()
+                .map(|a| (*a, a.default_keys()))
+                .collect(),
+        )
+    }
+}
+
+impl Bindings {
+    /// Saved bindings, with defaults for any actions missing from the file.
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        if let Some(Bindings(saved)) = storage::load_ron(BINDINGS_FILE) {
+            bindings.0.extend(saved);
+        }
+        bindings
+    }
+
+    pub fn save(&self) {
+        storage::save_ron(BINDINGS_FILE, self);
+    }
+
+    pub fn keys(&self, action: GameAction) -> &[KeyCode] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Bind a single key to an action, unbinding it from anything else that
+    /// could be pressed at the same time.
+    pub fn bind(&mut self, action: GameAction, key: KeyCode) {
+        for (other, keys) in self.0.iter_mut() {
+            if other.is_menu() == action.is_menu() {
+                keys.retain(|k| *k != key);
+            }
+        }
+        self.0.insert(action, vec![key]);
+    }
+
+    /// Whether any key for the action is held. A key can be pressed and released
+    /// within a single frame, so that counts too.
+    pub fn held(&self, input: &ButtonInput<KeyCode>, action: GameAction) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|k| input.pressed(*k) || input.just_pressed(*k))
+    }
+
+    pub fn just_pressed(&self, input: &ButtonInput<KeyCode>, action: GameAction) -> bool {
+        input.any_just_pressed(self.keys(action).iter().copied())
+    }
+
+    /// The keys for an action as they'd be written in the controls, ie "A/←".
+    pub fn describe(&self, action: GameAction) -> String {
+        let keys: Vec<_> = self.keys(action).iter().map(|k| key_name(*k)).collect();
+        if keys.is_empty() {
+            "-".to_string()
+        } else {
+            keys.join("/")
+        }
+    }
+}
+
+/// Reads actions from the keyboard through [Bindings], and from every connected
+/// gamepad.
+#[derive(SystemParam)]
+pub struct Actions<'w, 's> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    bindings: Res<'w, Bindings>,
+    gamepads: Query<'w, 's, &'static Gamepad>,
+}
+
+impl Actions<'_, '_> {
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn held(&self, action: GameAction) -> bool {
+        let buttons = action.buttons().iter().copied();
+        self.bindings.held(&self.keys, action)
+            || self.gamepads.iter().any(|pad| {
+                pad.any_pressed(buttons.clone())
+                    || pad.any_just_pressed(buttons.clone())
+                    || action
+                        .stick()
+                        .is_some_and(|dir| pad.left_stick().dot(dir) > STICK_DEADZONE)
+            })
+    }
+
+    pub fn just_pressed(&self, action: GameAction) -> bool {
+        let buttons = action.buttons().iter().copied();
+        self.bindings.just_pressed(&self.keys, action)
+            || self
+                .gamepads
+                .iter()
+                .any(|pad| pad.any_just_pressed(buttons.clone()))
+    }
+
+    /// Whether a button was just pressed on any connected gamepad.
+    pub fn pad_just_pressed(&self, button: GamepadButton) -> bool {
+        self.gamepads.iter().any(|pad| pad.just_pressed(button))
+    }
+
+    /// Whether Pause or Confirm was just pressed, for menus that use both.
+    /// Pause wins when both are, since a gamepad's Start button is bound to
+    /// each of them.
+    pub fn pause_or_confirm(&self) -> Option<GameAction> {
+        if self.just_pressed(GameAction::Pause) {
+            Some(GameAction::Pause)
+        } else if self.just_pressed(GameAction::Confirm) {
+            Some(GameAction::Confirm)
+        } else {
+            None
+        }
+    }
+
+    /// Which way a menu value should change, -1 for left and 1 for right. The
+    /// arrow keys always work, along with whatever movement is bound to.
+    pub fn menu_x(&self) -> isize {
+        let right =
+            self.keys.just_pressed(KeyCode::ArrowRight) || self.just_pressed(GameAction::MoveRight);
+        let left =
+            self.keys.just_pressed(KeyCode::ArrowLeft) || self.just_pressed(GameAction::MoveLeft);
+        right as isize - left as isize
+    }
+
+    /// Which way to move through the rows of a menu, -1 for up and 1 for down.
+    pub fn menu_y(&self) -> isize {
+        let down = self
+            .keys
+            .any_just_pressed([KeyCode::ArrowDown, KeyCode::KeyS])
+            || self.pad_just_pressed(GamepadButton::DPadDown);
+        let up = self
+            .keys
+            .any_just_pressed([KeyCode::ArrowUp, KeyCode::KeyW])
+            || self.pad_just_pressed(GamepadButton::DPadUp);
+        down as isize - up as isize
+    }
+}
+
+/// A short display name for a key.
+pub fn key_name(key: KeyCode) -> String {
+    let name = match key {
+        KeyCode::ArrowLeft => "←",
+        KeyCode::ArrowRight => "→",
+        KeyCode::ArrowUp => "↑",
+        KeyCode::ArrowDown => "↓",
+        KeyCode::ShiftLeft => "LShift",
+        KeyCode::ShiftRight => "RShift",
+        KeyCode::ControlLeft => "LCtrl",
+        KeyCode::ControlRight => "RCtrl",
+        KeyCode::AltLeft => "LAlt",
+        KeyCode::AltRight => "RAlt",
+        _ => "",
+    };
+    if !name.is_empty() {
+        return name.to_string();
+    }
+    let name = format!("{key:?}");
+    ["Key", "Digit"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .map_or(name.clone(), str::to_string)
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tetris_core::{Cheese, Game, GameMode};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::storage;
+
+/// How many scores are kept for each mode
+pub const HIGH_SCORE_COUNT: usize = 10;
+/// Letters in a name on the high score table
+pub const INITIALS_LEN: usize = 3;
+/// Characters a gamepad cycles through for each letter of a name
+pub const INITIALS_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const HIGH_SCORES_FILE: &str = "high_scores.ron";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: usize,
+    pub lines: usize,
+    pub level: usize,
+    /// Seconds played
+    pub time: f32,
+    /// Seconds since the unix epoch
+    pub date: u64,
+    /// Split times in seconds, for modes with a line goal
+    #[serde(default)]
+    pub splits: Vec<f32>,
+}
+
+impl HighScore {
+    /// An unnamed entry for a game that just ended.
+    pub fn from_game(game: &Game) -> Self {
+        let date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            name: String::new(),
+            score: game.scoring().score(),
+            lines: game.scoring().lines(),
+            level: game.scoring().level(),
+            time: game.elapsed(),
+            date,
+            splits: game.splits().to_vec(),
+        }
+    }
+
+    /// The date as year, month and day.
+    pub fn ymd(&self) -> (i64, u32, u32) {
+        civil_from_days((self.date / 86_400) as i64)
+    }
+}
+
+/// Whether a mode is a race, ranked by the fastest time rather than the
+/// highest score.
+pub fn ranks_by_time(mode: GameMode) -> bool {
+    matches!(mode, GameMode::Sprint | GameMode::Cheese(_))
+}
+
+/// Whether results of a mode are kept in a high score table.
+pub fn keeps_scores(mode: GameMode) -> bool {
+    table_key(mode).is_some()
+}
+
+/// The best scores for each mode, saved between runs.
+#[derive(Debug, Default, Resource, Serialize, Deserialize)]
+pub struct HighScores {
+    /// Tables keyed by mode name, best first. Only the standard cheese race
+    /// has a table, custom ones can't be compared against it.
+    tables: BTreeMap<String, Vec<HighScore>>,
+}
+
+impl HighScores {
+    pub fn load() -> Self {
+        storage::load_ron(HIGH_SCORES_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        storage::save_ron(HIGH_SCORES_FILE, self);
+    }
+
+    pub fn table(&self, mode: GameMode) -> &[HighScore] {
+        table_key(mode)
+            .and_then(|key| self.tables.get(key))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// The top entry of the mode's table, the personal best to beat.
+    pub fn best(&self, mode: GameMode) -> Option<&HighScore> {
+        self.table(mode).first()
+    }
+
+    /// The position the entry would take in the mode's table, if it makes it
+    /// in at all.
+    pub fn rank(&self, mode: GameMode, entry: &HighScore) -> Option<usize> {
+        table_key(mode)?;
+        let table = self.table(mode);
+        let rank = table
+            .iter()
+            .position(|other| is_better(mode, entry, other))
+            .unwrap_or(table.len());
+        (rank < HIGH_SCORE_COUNT).then_some(rank)
+    }
+
+    /// Add an entry to the mode's table, dropping the lowest entry if it's full.
+    pub fn insert(&mut self, mode: GameMode, entry: HighScore) -> Option<usize> {
+        let rank = self.rank(mode, &entry)?;
+        let key = table_key(mode)?;
+        let table = self.tables.entry(key.to_string()).or_default();
+        table.insert(rank, entry);
+        table.truncate(HIGH_SCORE_COUNT);
+        Some(rank)
+    }
+}
+
+/// The name of the mode's table, or `None` if its results aren't kept.
+fn table_key(mode: GameMode) -> Option<&'static str> {
+    match mode {
+        GameMode::Cheese(cheese) if cheese != Cheese::STANDARD => None,
+        mode => Some(mode.name()),
+    }
+}
+
+/// Ties go to the entry that got there first.
+fn is_better(mode: GameMode, a: &HighScore, b: &HighScore) -> bool {
+    if ranks_by_time(mode) {
+        a.time < b.time
+    } else {
+        a.score > b.score
+    }
+}
+
+/// Convert days since the unix epoch to a (year, month, day) date.
+///
+/// From Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
@@ -1,40 +1,57 @@
-mod board;
-mod piece;
-mod score;
-mod shuffle_bag;
-use std::collections::BTreeSet;
+mod bindings;
+mod high_scores;
+mod replay;
+mod storage;
 
 use bevy::audio::AudioSink;
-use bevy::input::ButtonState;
-use bevy::input::keyboard::KeyboardInput;
+use bevy::input::keyboard::{Key, KeyboardInput};
 use bevy::prelude::*;
+use bevy::window::{WindowFocused, WindowOccluded};
 use bevy::{DefaultPlugins, audio::Volume};
+use bevy_ascii_terminal::string::DecoratedString;
 use bevy_ascii_terminal::*;
-use board::{Board, EMPTY_SQUARE};
-use piece::*;
-use score::Scoring;
-use shuffle_bag::ShuffleBag;
+use bindings::{Actions, Bindings, GameAction};
+use high_scores::{
+    HighScore, HighScores, INITIALS_CHARS, INITIALS_LEN, keeps_scores, ranks_by_time,
+};
+use replay::{LastReplay, Recording, ReplayViewer, SEEK_TIME, SPEEDS};
+use serde::{Deserialize, Serialize};
+use tetris_core::mode::{SPLIT_LINES, SPRINT_LINES};
+use tetris_core::{
+    BOARD_HEIGHT, BOARD_WIDTH, Cheese, Clear, EMPTY_SQUARE, GARBAGE_SQUARE, Game, GameEvent,
+    GameMode, GravityCurve, Handling, Input, MAX_START_LEVEL, RandomizerKind, Replay, TICK_RATE,
+};
 
-pub const BOARD_WIDTH: usize = 10;
-pub const BOARD_HEIGHT: usize = 20;
 pub const BOARD_SIZE: UVec2 = UVec2::from_array([BOARD_WIDTH as u32, BOARD_HEIGHT as u32]);
-/// Blocks per second
-pub const FALL_SPEED_START: f32 = 1.5;
-pub const FALL_SPEED_ACCEL: f32 = 0.15;
-pub const FALL_SPEED_MAX: f32 = 12.5;
-pub const SOFT_DROP_SPEED: f32 = 10.0;
 pub const PIECE_GLYPH: char = '█';
 pub const BOARD_GLYPH: char = '█';
 pub const DROP_GHOST_GLYPH: char = '□';
 pub const DROP_GHOST_ALPHA: f32 = 0.09;
+/// Times filled lines flash before they're removed
+pub const LINE_CLEAR_FLASHES: f32 = 3.0;
 pub const MUSIC_VOLUME: f32 = 0.2;
 pub const SOUND_VOLUME: f32 = 0.5;
-
-#[derive(Component)]
-struct Active;
-
-#[derive(Component)]
-struct PlacePiece;
+/// Seconds the name of a clear stays in the score panel
+pub const CLEAR_MESSAGE_TIME: f32 = 2.0;
+/// The most pieces the next queue can show
+pub const MAX_PREVIEW: usize = 6;
+/// Longest any handling delay in the settings file can be, in seconds
+const MAX_HANDLING_DELAY: f32 = 5.0;
+/// Fastest soft drop the settings file can ask for, well past instant even at
+/// the slowest gravity
+const MAX_SOFT_DROP_FACTOR: f32 = 10_000.0;
+const SETTINGS_FILE: &str = "settings.ron";
+/// Piece colors, indexed by piece id
+pub const PIECE_COLORS: [Color; 7] = [
+    Color::srgb(0., 1., 1.),
+    Color::srgb(0., 0., 1.),
+    Color::srgb(1., 0.66, 0.),
+    Color::srgb(1., 1., 0.),
+    Color::srgb(0., 1., 0.),
+    Color::srgb(0.6, 0., 1.),
+    Color::srgb(1., 0., 0.),
+];
+pub const GARBAGE_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
 
 #[derive(Component)]
 struct BoardTerminal;
@@ -45,39 +62,233 @@ struct ScoreTerminal;
 #[derive(Component)]
 struct NextPieceTerminal;
 
-#[derive(Event)]
-struct NextPiece;
+#[derive(Component)]
+struct HoldPieceTerminal;
 
-#[derive(Default, Clone, Resource, Deref, DerefMut)]
-pub struct FallSpeed(f32);
+#[derive(Component)]
+struct ReplayTerminal;
 
+/// The game currently being played.
 #[derive(Resource, Deref, DerefMut)]
-pub struct RepeatTimer(Timer);
+pub struct Tetris(Game);
+
+/// Options for the next game, chosen on the title screen.
+#[derive(Default, Resource)]
+pub struct GameOptions {
+    /// Typed in seed, empty for a random one
+    seed: String,
+    mode: GameMode,
+    start_level: usize,
+    gravity: GravityCurve,
+    randomizer: RandomizerKind,
+}
+
+impl GameOptions {
+    /// An empty recording of a new game with these options. The game itself
+    /// comes from [Replay::start].
+    fn new_replay(&self, settings: &Settings) -> Replay {
+        let seed = self.seed.parse().unwrap_or_else(|_| rand::random());
+        let mode = match self.mode {
+            GameMode::Cheese(_) => GameMode::Cheese(settings.cheese),
+            mode => mode,
+        };
+        Replay::new(
+            mode,
+            self.gravity,
+            self.start_level,
+            self.randomizer,
+            seed,
+            settings.handling,
+        )
+    }
+}
+
+/// A result that made it onto the high score table, waiting for the player's
+/// initials.
+#[derive(Default, Resource)]
+pub struct PendingScore(Option<HighScore>);
+
+/// The high score table being viewed, and the row to highlight if it was just
+/// added.
+#[derive(Default, Resource)]
+pub struct ScoreView {
+    mode: GameMode,
+    highlight: Option<usize>,
+}
 
-pub enum DropType {
-    Normal,
-    Soft,
-    Hard,
+/// The row selected on the controls screen, and whether it's waiting for a key
+/// to bind.
+#[derive(Default, Resource)]
+struct ControlsCursor {
+    selected: usize,
+    listening: bool,
 }
 
+/// The name of the last clear, shown in the score panel for a moment.
+#[derive(Resource)]
+pub struct ClearMessage {
+    text: Option<String>,
+    perfect_clear: bool,
+    timer: Timer,
+}
+
+impl Default for ClearMessage {
+    fn default() -> Self {
+        Self {
+            text: None,
+            perfect_clear: false,
+            timer: Timer::from_seconds(CLEAR_MESSAGE_TIME, TimerMode::Once),
+        }
+    }
+}
+
+impl ClearMessage {
+    fn show(&mut self, clear: &Clear) {
+        if let Some(name) = clear.name() {
+            self.text = Some(name);
+            self.perfect_clear = clear.perfect_clear;
+            self.timer.reset();
+        }
+    }
+}
+
+/// Buttons that were held when play started or resumed, as [Input::to_bits].
+/// They're ignored until released so they don't act on the piece.
+#[derive(Default, Resource)]
+struct SuppressedInput(u8);
+
+/// The rows of the title screen that can be changed with ←/→.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum TitleOption {
+    #[default]
+    Mode,
+    Level,
+    Gravity,
+    Randomizer,
+    Preview,
+}
+
+impl TitleOption {
+    const ALL: [TitleOption; 5] = [
+        TitleOption::Mode,
+        TitleOption::Level,
+        TitleOption::Gravity,
+        TitleOption::Randomizer,
+        TitleOption::Preview,
+    ];
+}
+
+/// The title screen row currently selected.
+#[derive(Default, Resource)]
+struct TitleCursor(TitleOption);
+
+/// A choice on the pause menu.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum PauseOption {
+    #[default]
+    Resume,
+    Restart,
+    Quit,
+}
+
+impl PauseOption {
+    const ALL: [PauseOption; 3] = [PauseOption::Resume, PauseOption::Restart, PauseOption::Quit];
+
+    fn name(&self) -> &'static str {
+        match self {
+            PauseOption::Resume => "Resume",
+            PauseOption::Restart => "Restart",
+            PauseOption::Quit => "Quit to Title",
+        }
+    }
+}
+
+/// The pause menu row currently selected.
+#[derive(Default, Resource)]
+struct PauseCursor(PauseOption);
+
 #[derive(Debug, States, PartialEq, Eq, Hash, Clone)]
 enum GameState {
     Setup,
     Title,
     Playing,
+    Paused,
+    Replay,
     GameOver,
+    Finished,
+    EnterInitials,
+    ViewHighScores,
+    Controls,
 }
 
 #[derive(Component)]
 struct Music;
 
-#[derive(Resource)]
+/// Player preferences, saved whenever they change.
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     music_volume: f32,
     sound_volume: f32,
+    handling: Handling,
+    /// How many upcoming pieces the next queue shows, 1 to [MAX_PREVIEW]
+    preview_count: usize,
+    /// Garbage rows, their height and how often the hole moves in a cheese
+    /// race
+    cheese: Cheese,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            music_volume: 0.0,
+            sound_volume: 0.0,
+            handling: Handling::default(),
+            preview_count: MAX_PREVIEW,
+            cheese: Cheese::STANDARD,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let mut settings: Settings = storage::load_ron(SETTINGS_FILE).unwrap_or_default();
+        settings.preview_count = settings.preview_count.clamp(1, MAX_PREVIEW);
+        let cheese = &mut settings.cheese;
+        cheese.rows = cheese.rows.max(1);
+        cheese.height = cheese.height.clamp(1, BOARD_HEIGHT);
+        cheese.messiness = cheese.messiness.min(100);
+
+        // A hand edited file could hold anything, including NaN
+        let defaults = Handling::default();
+        let delay = |secs: f32, default: f32| {
+            if secs.is_nan() {
+                default
+            } else {
+                secs.clamp(0.0, MAX_HANDLING_DELAY)
+            }
+        };
+        let handling = &mut settings.handling;
+        handling.lock_delay = delay(handling.lock_delay, defaults.lock_delay);
+        handling.das = delay(handling.das, defaults.das);
+        handling.arr = delay(handling.arr, defaults.arr);
+        handling.entry_delay = delay(handling.entry_delay, defaults.entry_delay);
+        handling.line_clear_delay = delay(handling.line_clear_delay, defaults.line_clear_delay);
+        handling.soft_drop_factor = if handling.soft_drop_factor.is_nan() {
+            defaults.soft_drop_factor
+        } else {
+            handling.soft_drop_factor.clamp(1.0, MAX_SOFT_DROP_FACTOR)
+        };
+        settings
+    }
+
+    pub fn save(&self) {
+        storage::save_ron(SETTINGS_FILE, self);
+    }
 }
 
 fn main() {
+    let replay = GameOptions::default().new_replay(&Settings::default());
     App::new()
         .add_plugins((
             DefaultPlugins.set(WindowPlugin {
@@ -90,40 +301,81 @@ fn main() {
             TerminalPlugins,
         ))
         .insert_resource(ClearColor(Color::BLACK))
-        .init_resource::<ShuffleBag>()
-        .init_resource::<Scoring>()
-        .insert_resource(FallSpeed(FALL_SPEED_START))
-        .insert_resource(Board {
-            state: vec![0; BOARD_WIDTH * BOARD_HEIGHT],
-        })
-        .insert_resource(Settings {
-            music_volume: 0.0,
-            sound_volume: 0.0,
-        })
-        .add_event::<NextPiece>()
+        .insert_resource(Time::<Fixed>::from_hz(TICK_RATE as f64))
+        .insert_resource(Tetris(replay.start()))
+        .insert_resource(Recording(replay))
+        .insert_resource(LastReplay::load())
+        .init_resource::<SuppressedInput>()
+        .init_resource::<ClearMessage>()
+        .init_resource::<GameOptions>()
+        .init_resource::<TitleCursor>()
+        .init_resource::<PauseCursor>()
+        .init_resource::<PendingScore>()
+        .init_resource::<ScoreView>()
+        .insert_resource(HighScores::load())
+        .insert_resource(Settings::load())
+        .insert_resource(Bindings::load())
+        .init_resource::<ControlsCursor>()
         .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                save_settings
+                    .run_if(resource_changed::<Settings>.and(not(resource_added::<Settings>))),
+                save_bindings
+                    .run_if(resource_changed::<Bindings>.and(not(resource_added::<Bindings>))),
+            ),
+        )
         .add_systems(OnEnter(GameState::Title), restart_to_title.after(setup))
         .add_systems(Update, title_input.run_if(in_state(GameState::Title)))
-        .add_systems(OnEnter(GameState::GameOver), game_over)
-        .add_systems(OnEnter(GameState::Playing), next_piece)
+        .add_systems(
+            OnEnter(GameState::GameOver),
+            (game_over, check_high_score, save_replay).chain(),
+        )
+        .add_systems(
+            OnEnter(GameState::Finished),
+            (finished, check_high_score, save_replay).chain(),
+        )
+        .add_systems(OnEnter(GameState::Replay), watch_replay)
+        .add_systems(OnExit(GameState::Replay), stop_replay)
+        .add_systems(OnEnter(GameState::Paused), pause)
+        .add_systems(OnExit(GameState::Paused), unpause)
+        .add_systems(OnEnter(GameState::EnterInitials), enter_initials)
+        .add_systems(OnEnter(GameState::ViewHighScores), view_high_scores)
+        .add_systems(OnEnter(GameState::Controls), view_controls)
         .add_systems(
             Update,
             (
-                options_input,
-                game_over_input.run_if(in_state(GameState::GameOver)),
+                // Don't toggle sound while typing in initials or rebinding keys
+                options_input.run_if(not(
+                    in_state(GameState::EnterInitials).or(in_state(GameState::Controls))
+                )),
+                game_over_input
+                    .run_if(in_state(GameState::GameOver).or(in_state(GameState::Finished))),
+                initials_input.run_if(in_state(GameState::EnterInitials)),
+                high_scores_input.run_if(in_state(GameState::ViewHighScores)),
+                controls_input.run_if(in_state(GameState::Controls)),
+                pause_input.run_if(in_state(GameState::Paused)),
             ),
         )
         .add_systems(
-            PreUpdate,
-            next_piece
-                .run_if(on_event::<NextPiece>)
-                .run_if(in_state(GameState::Playing)),
+            FixedUpdate,
+            update_game.run_if(in_state(GameState::Playing)),
         )
         .add_systems(
             Update,
-            (movement, place, draw_board, draw_score, draw_next)
+            (
+                pause_game.run_if(in_state(GameState::Playing)),
+                replay_input.run_if(in_state(GameState::Replay)),
+                tick_clear_message,
+                draw_board,
+                draw_score,
+                draw_next,
+                draw_hold,
+                draw_replay.run_if(in_state(GameState::Replay)),
+            )
                 .chain()
-                .run_if(in_state(GameState::Playing)),
+                .run_if(in_state(GameState::Playing).or(in_state(GameState::Replay))),
         )
         .insert_state(GameState::Setup)
         .run();
@@ -140,352 +392,1163 @@ fn setup(mut commands: Commands) {
     ));
 
     commands.spawn((
-        Terminal::new([7, 6]),
+        Terminal::new([12, 12]),
         ScoreTerminal,
         TerminalMeshPivot::BottomLeft,
         TerminalBorder::single_line(),
-        SetTerminalGridPosition(IVec2::new(BOARD_WIDTH as i32 + 2, 0)),
+        SetTerminalGridPosition(IVec2::new(-14, 0)),
     ));
 
     commands.spawn((
-        Terminal::new([7, 6]),
+        Terminal::new(next_queue_size(MAX_PREVIEW)),
         SetTerminalGridPosition(IVec2::new(BOARD_WIDTH as i32 + 2, BOARD_HEIGHT as i32 + 2)),
         NextPieceTerminal,
         TerminalMeshPivot::TopLeft,
         TerminalBorder::single_line(),
     ));
 
+    commands.spawn((
+        Terminal::new([7, 6]),
+        SetTerminalGridPosition(IVec2::new(-9, BOARD_HEIGHT as i32 + 2)),
+        HoldPieceTerminal,
+        TerminalMeshPivot::TopLeft,
+        TerminalBorder::single_line(),
+    ));
+
     commands.spawn(TerminalCamera::new());
 
     commands.set_state(GameState::Title);
 }
 
 // on event: Restart
-#[allow(clippy::too_many_arguments)]
 fn restart_to_title(
-    q_pieces: Query<Entity, With<Piece>>,
-    mut board: ResMut<Board>,
-    mut bag: ResMut<ShuffleBag>,
-    mut score: ResMut<Scoring>,
-    mut fall_speed: ResMut<FallSpeed>,
-    mut commands: Commands,
+    mut clear_message: ResMut<ClearMessage>,
+    options: Res<GameOptions>,
+    cursor: Res<TitleCursor>,
+    settings: Res<Settings>,
+    bindings: Res<Bindings>,
     mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    mut q_hold_term: Query<&mut Terminal, (With<HoldPieceTerminal>, Without<BoardTerminal>)>,
 ) {
-    for entity in &q_pieces {
-        commands.entity(entity).despawn();
-    }
-
-    board.reset();
-    *bag = ShuffleBag::default();
-    *fall_speed = FallSpeed(FALL_SPEED_START);
-    *score = Scoring::default();
+    *clear_message = ClearMessage::default();
+    q_hold_term.single_mut().clear();
 
     let mut term = q_board_term.single_mut();
-    term.clear();
     term.resize([BOARD_WIDTH + 20, BOARD_HEIGHT]);
-    term.put_string([0, 6].pivot(Pivot::Center), "ASCII TETRIS".fg(color::RED));
-    term.put_string([0, 4].pivot(Pivot::Center), "Controls:");
-    term.put_string(
-        [0, 0].pivot(Pivot::Center),
-        "Movement: A/D/←/→
-Soft Drop: S/↓
-Hard Drop: Space
-Rotate: Q/E/Z/X
+    draw_title(&mut term, &options, &settings, &bindings, cursor.0);
+}
+
+fn draw_title(
+    term: &mut Terminal,
+    options: &GameOptions,
+    settings: &Settings,
+    bindings: &Bindings,
+    selected: TitleOption,
+) {
+    term.clear();
+    term.put_string([0, 9].pivot(Pivot::Center), "ASCII TETRIS".fg(color::RED));
 
-Toggle Music: M
-Toggle Sound: N
+    let seed = if options.seed.is_empty() {
+        "Random"
+    } else {
+        &options.seed
+    };
+    for (y, option) in [7, 6, 5, 4, 3].into_iter().zip(TitleOption::ALL) {
+        let text = match option {
+            TitleOption::Mode => format!("Mode: < {} >", options.mode.name()),
+            TitleOption::Level => format!("Level: < {} >", options.start_level),
+            TitleOption::Gravity => format!("Gravity: < {} >", options.gravity.name()),
+            TitleOption::Randomizer => {
+                format!("Randomizer: < {} >", options.randomizer.name())
+            }
+            TitleOption::Preview => format!("Preview: < {} >", settings.preview_count),
+        };
+        let color = if option == selected {
+            color::YELLOW
+        } else {
+            color::WHITE
+        };
+        term.put_string([0, y].pivot(Pivot::Center), text.fg(color));
+    }
+    term.put_string([0, 2].pivot(Pivot::Center), format!("Seed: {seed}"));
 
-Press Space to Begin",
+    let keys = |action| bindings.describe(action);
+    term.put_string(
+        [0, -5].pivot(Pivot::Center),
+        format!(
+            "Movement: {} {}
+Soft Drop: {}
+Hard Drop: {}
+Rotate: {} {} {}
+Hold: {}
+Pause: {}
+Toggle Music/Sound: {}/{}
+Options: ↑/↓ ←/→
+Set Seed: 0-9/Backspace
+High Scores: H  Controls: K
+Watch Replay: R
+Press {} to Begin",
+            keys(GameAction::MoveLeft),
+            keys(GameAction::MoveRight),
+            keys(GameAction::SoftDrop),
+            keys(GameAction::HardDrop),
+            keys(GameAction::RotateCCW),
+            keys(GameAction::RotateCW),
+            keys(GameAction::Rotate180),
+            keys(GameAction::Hold),
+            keys(GameAction::Pause),
+            keys(GameAction::ToggleMusic),
+            keys(GameAction::ToggleSound),
+            keys(GameAction::Confirm),
+        ),
     );
 }
 
+#[allow(clippy::too_many_arguments)]
 fn title_input(
     mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
     input: Res<ButtonInput<KeyCode>>,
     mut commands: Commands,
     server: Res<AssetServer>,
-    settings: Res<Settings>,
+    mut settings: ResMut<Settings>,
+    mut options: ResMut<GameOptions>,
+    mut cursor: ResMut<TitleCursor>,
+    mut score_view: ResMut<ScoreView>,
+    actions: Actions,
+    mut game: ResMut<Tetris>,
+    mut recording: ResMut<Recording>,
+    mut suppressed: ResMut<SuppressedInput>,
+    last_replay: Res<LastReplay>,
 ) {
-    if input.just_pressed(KeyCode::Space) {
+    const DIGITS: [[KeyCode; 2]; 10] = [
+        [KeyCode::Digit0, KeyCode::Numpad0],
+        [KeyCode::Digit1, KeyCode::Numpad1],
+        [KeyCode::Digit2, KeyCode::Numpad2],
+        [KeyCode::Digit3, KeyCode::Numpad3],
+        [KeyCode::Digit4, KeyCode::Numpad4],
+        [KeyCode::Digit5, KeyCode::Numpad5],
+        [KeyCode::Digit6, KeyCode::Numpad6],
+        [KeyCode::Digit7, KeyCode::Numpad7],
+        [KeyCode::Digit8, KeyCode::Numpad8],
+        [KeyCode::Digit9, KeyCode::Numpad9],
+    ];
+    // Enough for any u64, digits that would overflow one are ignored
+    const MAX_SEED_DIGITS: usize = 20;
+
+    let mut changed = false;
+    for (digit, keys) in DIGITS.iter().enumerate() {
+        if !input.any_just_pressed(*keys) || options.seed.len() >= MAX_SEED_DIGITS {
+            continue;
+        }
+        let mut seed = options.seed.clone();
+        seed.push(char::from_digit(digit as u32, 10).unwrap());
+        if seed.parse::<u64>().is_ok() {
+            options.seed = seed;
+            changed = true;
+        }
+    }
+    if input.just_pressed(KeyCode::Backspace) {
+        changed |= options.seed.pop().is_some();
+    }
+
+    let row_step = actions.menu_y();
+    if row_step != 0 {
+        let all = TitleOption::ALL;
+        let i = all.iter().position(|o| *o == cursor.0).unwrap();
+        cursor.0 = all[(i as isize + row_step).rem_euclid(all.len() as isize) as usize];
+        changed = true;
+    }
+
+    let step = actions.menu_x();
+    if step != 0 {
+        match cursor.0 {
+            TitleOption::Mode => {
+                let all = GameMode::ALL;
+                let i = all.iter().position(|m| *m == options.mode).unwrap();
+                options.mode = all[(i as isize + step).rem_euclid(all.len() as isize) as usize];
+            }
+            TitleOption::Level => {
+                options.start_level = options
+                    .start_level
+                    .saturating_add_signed(step)
+                    .min(MAX_START_LEVEL);
+            }
+            TitleOption::Gravity => {
+                let all = GravityCurve::ALL;
+                let i = all.iter().position(|g| *g == options.gravity).unwrap();
+                options.gravity = all[(i as isize + step).rem_euclid(all.len() as isize) as usize];
+            }
+            TitleOption::Randomizer => {
+                let all = RandomizerKind::ALL;
+                let i = all.iter().position(|r| *r == options.randomizer).unwrap();
+                options.randomizer =
+                    all[(i as isize + step).rem_euclid(all.len() as isize) as usize];
+            }
+            TitleOption::Preview => {
+                settings.preview_count = settings
+                    .preview_count
+                    .saturating_add_signed(step)
+                    .clamp(1, MAX_PREVIEW);
+            }
+        }
+        changed = true;
+    }
+
+    if changed {
+        draw_title(
+            &mut q_board_term.single_mut(),
+            &options,
+            &settings,
+            actions.bindings(),
+            cursor.0,
+        );
+    }
+
+    if input.just_pressed(KeyCode::KeyH) {
+        *score_view = ScoreView {
+            mode: options.mode,
+            highlight: None,
+        };
+        commands.set_state(GameState::ViewHighScores);
+        return;
+    }
+
+    if input.just_pressed(KeyCode::KeyK) {
+        commands.set_state(GameState::Controls);
+        return;
+    }
+
+    if input.just_pressed(KeyCode::KeyR) && last_replay.0.is_some() {
+        commands.set_state(GameState::Replay);
+        return;
+    }
+
+    if actions.just_pressed(GameAction::Confirm) {
+        recording.0 = options.new_replay(&settings);
+        *game = Tetris(recording.0.start());
+        // Confirm shares buttons with the game, ie hard drop
+        suppressed.0 = read_input(&actions).to_bits();
         commands.set_state(GameState::Playing);
         let mut term = q_board_term.single_mut();
         term.clear();
         term.resize([BOARD_WIDTH, BOARD_HEIGHT]);
-        commands.spawn((
-            AudioPlayer::new(server.load("start.wav")),
-            PlaybackSettings::ONCE.with_volume(Volume::new(settings.sound_volume)),
-        ));
-        commands.spawn((
-            AudioPlayer::new(server.load("theme.ogg")),
-            PlaybackSettings::LOOP.with_volume(Volume::new(settings.music_volume)),
-            Music,
-        ));
+        start_music(&mut commands, &server, &settings);
     }
 }
 
+/// Play the start jingle and loop the theme for a new game.
+fn start_music(commands: &mut Commands, server: &AssetServer, settings: &Settings) {
+    commands.spawn((
+        AudioPlayer::new(server.load("start.wav")),
+        PlaybackSettings::ONCE.with_volume(Volume::new(settings.sound_volume)),
+    ));
+    commands.spawn((
+        AudioPlayer::new(server.load("theme.ogg")),
+        PlaybackSettings::LOOP.with_volume(Volume::new(settings.music_volume)),
+        Music,
+    ));
+}
+
 fn options_input(
-    input: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
     mut settings: ResMut<Settings>,
     q_music: Query<&AudioSink, With<Music>>,
 ) {
-    if input.just_pressed(KeyCode::KeyM) {
+    if actions.just_pressed(GameAction::ToggleMusic) {
         settings.music_volume = MUSIC_VOLUME - settings.music_volume;
         q_music.iter().for_each(|player| {
             player.set_volume(settings.music_volume);
         });
     }
 
-    if input.just_pressed(KeyCode::KeyN) {
+    if actions.just_pressed(GameAction::ToggleSound) {
         settings.sound_volume = SOUND_VOLUME - settings.sound_volume;
     }
 }
 
-fn game_over_input(input: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
-    if input.just_pressed(KeyCode::Space) {
-        commands.set_state(GameState::Title);
+fn save_settings(settings: Res<Settings>) {
+    settings.save();
+}
+
+fn save_bindings(bindings: Res<Bindings>) {
+    bindings.save();
+}
+
+fn game_over_input(actions: Actions, pending: Res<PendingScore>, mut commands: Commands) {
+    if actions.just_pressed(GameAction::Confirm) {
+        if pending.0.is_some() {
+            commands.set_state(GameState::EnterInitials);
+        } else {
+            commands.set_state(GameState::Title);
+        }
     }
 }
 
-fn next_piece(
-    q_piece: Query<&Piece, With<Active>>,
-    mut bag: ResMut<ShuffleBag>,
-    mut commands: Commands,
+/// Keep the result of the game that just ended if it's good enough for the
+/// high score table. Races only count if they were finished.
+fn check_high_score(
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    game: Res<Tetris>,
+    state: Res<State<GameState>>,
+    high_scores: Res<HighScores>,
+    mut pending: ResMut<PendingScore>,
 ) {
-    if !q_piece.is_empty() {
-        return;
+    let entry = HighScore::from_game(&game);
+    let qualifies = if ranks_by_time(game.mode()) {
+        *state.get() == GameState::Finished
+    } else {
+        entry.score > 0
+    };
+
+    pending.0 = None;
+    if qualifies && high_scores.rank(game.mode(), &entry).is_some() {
+        q_board_term.single_mut().put_string(
+            [0, -6].pivot(Pivot::Center),
+            "New High Score!".fg(color::YELLOW),
+        );
+        pending.0 = Some(entry);
     }
-    let mut piece = bag.get_piece();
-    piece.pos.x = BOARD_WIDTH as f32 / 2.0;
-    piece.pos.y = BOARD_HEIGHT as f32 + 2.0;
+}
 
-    commands.spawn((piece, Active));
+fn enter_initials(
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    pending: Res<PendingScore>,
+) {
+    if let Some(entry) = &pending.0 {
+        draw_initials(&mut q_board_term.single_mut(), entry);
+    }
+}
+
+fn draw_initials(term: &mut Terminal, entry: &HighScore) {
+    term.clear();
+
+    let initials: String = (0..INITIALS_LEN)
+        .map(|i| entry.name.chars().nth(i).unwrap_or('_'))
+        .flat_map(|c| [c, ' '])
+        .collect();
+    term.put_string(
+        [0, 3].pivot(Pivot::Center),
+        "New High Score!".fg(color::YELLOW),
+    );
+    term.put_string([0, 1].pivot(Pivot::Center), "Enter your initials:");
+    term.put_string(
+        [0, -1].pivot(Pivot::Center),
+        initials.trim_end().fg(color::YELLOW),
+    );
+    term.put_string([0, -3].pivot(Pivot::Center), "Press Enter to save");
+    term.put_string([0, -5].pivot(Pivot::Center), "Gamepad: ↑↓ letter → next");
+    term.put_string([0, -6].pivot(Pivot::Center), "← delete, Start to save");
 }
 
 #[allow(clippy::too_many_arguments)]
-fn movement(
+fn initials_input(
+    mut keys: EventReader<KeyboardInput>,
+    actions: Actions,
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    game: Res<Tetris>,
+    mut pending: ResMut<PendingScore>,
+    mut high_scores: ResMut<HighScores>,
+    mut score_view: ResMut<ScoreView>,
     mut commands: Commands,
-    input: Res<ButtonInput<KeyCode>>,
-    fall_speed: Res<FallSpeed>,
-    board: ResMut<Board>,
-    mut q_piece: Query<(Entity, &mut Piece), With<Active>>,
-    mut score: ResMut<Scoring>,
-    time: Res<Time>,
-    mut key_events: EventReader<KeyboardInput>,
 ) {
-    let dt = time.delta_secs();
-    for (entity, mut piece) in &mut q_piece {
-        // Manual input polling handles key repeat automatically, feels much more
-        // responsive for movement
-        for evt in key_events.read() {
-            if evt.state == ButtonState::Pressed {
-                let right =
-                    (evt.key_code == KeyCode::KeyD || evt.key_code == KeyCode::ArrowRight) as i32;
-                let left =
-                    (evt.key_code == KeyCode::KeyA || evt.key_code == KeyCode::ArrowLeft) as i32;
-                let hor = right - left;
-                if hor != 0
-                    && can_move(
-                        &board,
-                        piece.pos,
-                        grid_points(&piece.points),
-                        IVec2::new(hor, 0),
-                    )
-                {
-                    piece.pos.x += hor as f32;
+    let Some(entry) = pending.0.as_mut() else {
+        return;
+    };
+
+    let mut changed = false;
+    let mut done = false;
+    for key in keys.read().filter(|k| k.state.is_pressed()) {
+        match &key.logical_key {
+            Key::Character(s) => {
+                for c in s.chars().filter(char::is_ascii_alphanumeric) {
+                    if entry.name.len() < INITIALS_LEN {
+                        entry.name.push(c.to_ascii_uppercase());
+                        changed = true;
+                    }
                 }
             }
+            Key::Backspace => changed |= entry.name.pop().is_some(),
+            Key::Enter => done |= !entry.name.is_empty(),
+            _ => {}
         }
+    }
+
+    // Gamepads have no keys to type with, so up and down cycle the last letter
+    // and right starts a new one
+    let pad = |button| actions.pad_just_pressed(button);
+    let step = pad(GamepadButton::DPadUp) as isize - pad(GamepadButton::DPadDown) as isize;
+    let new_letter = pad(GamepadButton::DPadRight) && entry.name.len() < INITIALS_LEN;
+    if new_letter || (step != 0 && entry.name.is_empty()) {
+        entry.name.push(INITIALS_CHARS[0] as char);
+        changed = true;
+    } else if step != 0 {
+        let last = entry.name.pop().unwrap() as u8;
+        let i = INITIALS_CHARS.iter().position(|c| *c == last).unwrap_or(0);
+        let i = (i as isize + step).rem_euclid(INITIALS_CHARS.len() as isize);
+        entry.name.push(INITIALS_CHARS[i as usize] as char);
+        changed = true;
+    }
+    if pad(GamepadButton::DPadLeft) {
+        changed |= entry.name.pop().is_some();
+    }
+    if pad(GamepadButton::Start) || pad(GamepadButton::South) {
+        done |= !entry.name.is_empty();
+    }
+
+    if done {
+        let entry = pending.0.take().unwrap();
+        *score_view = ScoreView {
+            mode: game.mode(),
+            highlight: high_scores.insert(game.mode(), entry),
+        };
+        high_scores.save();
+        commands.set_state(GameState::ViewHighScores);
+    } else if changed {
+        draw_initials(&mut q_board_term.single_mut(), entry);
+    }
+}
+
+fn view_high_scores(
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    high_scores: Res<HighScores>,
+    view: Res<ScoreView>,
+    bindings: Res<Bindings>,
+) {
+    let mut term = q_board_term.single_mut();
+    term.resize([BOARD_WIDTH + 20, BOARD_HEIGHT]);
+    draw_high_scores(&mut term, &high_scores, &view, &bindings);
+}
+
+fn view_controls(
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    bindings: Res<Bindings>,
+    mut cursor: ResMut<ControlsCursor>,
+) {
+    cursor.listening = false;
+    let mut term = q_board_term.single_mut();
+    term.resize([BOARD_WIDTH + 20, BOARD_HEIGHT]);
+    draw_controls(&mut term, &bindings, &cursor);
+}
 
-        let rot = input.any_just_pressed([KeyCode::KeyE, KeyCode::KeyX]) as i32
-            - input.any_just_pressed([KeyCode::KeyQ, KeyCode::KeyZ]) as i32;
-        let rot = match rot {
-            1 => Some(Rotation::Clockwise),
-            -1 => Some(Rotation::Counterclockwise),
-            _ => None,
+fn draw_controls(term: &mut Terminal, bindings: &Bindings, cursor: &ControlsCursor) {
+    term.clear();
+    term.put_string([0, 9].pivot(Pivot::Center), "Controls".fg(color::RED));
+
+    for (i, action) in GameAction::ALL.iter().enumerate() {
+        let selected = i == cursor.selected;
+        let keys = if selected && cursor.listening {
+            "Press a key...".to_string()
+        } else {
+            bindings.describe(*action)
+        };
+        let keys: String = keys.chars().take(16).collect();
+        let color = if selected {
+            color::YELLOW
+        } else {
+            color::WHITE
         };
-        if let Some(rot) = rot {
-            piece.rotate(rot);
-            if !can_move(&board, piece.pos, grid_points(&piece.points), [0, 0]) {
-                piece.rotate(rot.opposite());
+        term.put_string(
+            [0, 7 - i as i32].pivot(Pivot::Center),
+            format!("{:<11} {keys:<16}", action.name()).fg(color),
+        );
+    }
+
+    term.put_string([0, -6].pivot(Pivot::Center), "Select: ↑/↓  Rebind: Enter");
+    term.put_string([0, -7].pivot(Pivot::Center), "Reset Defaults: Backspace");
+    term.put_string([0, -8].pivot(Pivot::Center), "Press Escape to return");
+}
+
+/// The controls screen uses fixed keys so it can't be made unusable by
+/// rebinding.
+fn controls_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    mut bindings: ResMut<Bindings>,
+    mut cursor: ResMut<ControlsCursor>,
+    mut commands: Commands,
+) {
+    let action = GameAction::ALL[cursor.selected];
+    if cursor.listening {
+        if let Some(key) = input.get_just_pressed().next() {
+            if *key != KeyCode::Escape {
+                bindings.bind(action, *key);
             }
+            cursor.listening = false;
+            draw_controls(&mut q_board_term.single_mut(), &bindings, &cursor);
         }
+        return;
+    }
 
-        let mut fall = fall_speed.0 + FALL_SPEED_ACCEL * score.level() as f32;
-        let drop_type =
-            if input.any_pressed([KeyCode::KeyS, KeyCode::ArrowDown]) && piece_is_visible(&piece) {
-                fall = (fall + SOFT_DROP_SPEED) * dt;
-                DropType::Soft
-            } else if input.just_pressed(KeyCode::Space) {
-                fall = 30.0;
-                DropType::Hard
-            } else {
-                fall *= dt;
-                DropType::Normal
-            };
-
-        let (pos, hit, lines_moved) = try_drop(piece.pos, &piece.points, &board, fall);
-
-        if lines_moved != 0 {
-            match drop_type {
-                DropType::Normal => (),
-                DropType::Soft => score.soft_drop(lines_moved),
-                DropType::Hard => score.hard_drop(lines_moved),
-            };
-        }
+    if input.just_pressed(KeyCode::Escape) {
+        commands.set_state(GameState::Title);
+        return;
+    }
 
-        if hit {
-            commands.entity(entity).insert(PlacePiece);
-        }
+    let mut changed = false;
+    let step = input.just_pressed(KeyCode::ArrowDown) as isize
+        - input.just_pressed(KeyCode::ArrowUp) as isize;
+    if step != 0 {
+        let count = GameAction::ALL.len() as isize;
+        cursor.selected = (cursor.selected as isize + step).rem_euclid(count) as usize;
+        changed = true;
+    }
+    if input.just_pressed(KeyCode::Enter) {
+        cursor.listening = true;
+        changed = true;
+    }
+    if input.just_pressed(KeyCode::Backspace) {
+        *bindings = Bindings::default();
+        changed = true;
+    }
 
-        piece.pos = pos;
+    if changed {
+        draw_controls(&mut q_board_term.single_mut(), &bindings, &cursor);
     }
 }
 
+fn draw_high_scores(
+    term: &mut Terminal,
+    high_scores: &HighScores,
+    view: &ScoreView,
+    bindings: &Bindings,
+) {
+    term.clear();
+
+    let mode = view.mode;
+    term.put_string(
+        [0, 9].pivot(Pivot::Center),
+        format!("< {} High Scores >", mode.name()).fg(color::RED),
+    );
+
+    let by_time = ranks_by_time(mode);
+    let header = if by_time {
+        format!(
+            "{:>2} {:<3} {:>8} {:>3} {:<8}",
+            "#", "", "Time", "Ln", "Date"
+        )
+    } else {
+        format!(
+            "{:>2} {:<3} {:>7} {:>3} {:>2} {:<8}",
+            "#", "", "Score", "Ln", "Lv", "Date"
+        )
+    };
+    term.put_string([0, 7].pivot(Pivot::Center), header);
+
+    let table = high_scores.table(mode);
+    if table.is_empty() {
+        term.put_string([0, 4].pivot(Pivot::Center), "No scores yet");
+    }
+    for (i, entry) in table.iter().enumerate() {
+        let (year, month, day) = entry.ymd();
+        let date = format!("{:02}-{month:02}-{day:02}", year % 100);
+        let row = if by_time {
+            format!(
+                "{:>2} {:<3} {:>8} {:>3} {date}",
+                i + 1,
+                entry.name,
+                format_time(entry.time),
+                entry.lines
+            )
+        } else {
+            format!(
+                "{:>2} {:<3} {:>7} {:>3} {:>2} {date}",
+                i + 1,
+                entry.name,
+                entry.score,
+                entry.lines,
+                entry.level
+            )
+        };
+        let color = if view.highlight == Some(i) {
+            color::YELLOW
+        } else {
+            color::WHITE
+        };
+        term.put_string([0, 6 - i as i32].pivot(Pivot::Center), row.fg(color));
+    }
+
+    term.put_string([0, -7].pivot(Pivot::Center), "Mode: ←/→");
+    term.put_string(
+        [0, -8].pivot(Pivot::Center),
+        format!("Press {} to return", bindings.describe(GameAction::Confirm)),
+    );
+}
+
+fn high_scores_input(
+    input: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    high_scores: Res<HighScores>,
+    mut view: ResMut<ScoreView>,
+    mut commands: Commands,
+) {
+    if input.any_just_pressed([KeyCode::Escape, KeyCode::KeyH])
+        || actions.just_pressed(GameAction::Confirm)
+    {
+        commands.set_state(GameState::Title);
+        return;
+    }
+
+    let step = actions.menu_x();
+    if step != 0 {
+        let all = GameMode::ALL;
+        let i = all.iter().position(|m| *m == view.mode).unwrap_or(0);
+        view.mode = all[(i as isize + step).rem_euclid(all.len() as isize) as usize];
+        view.highlight = None;
+        draw_high_scores(
+            &mut q_board_term.single_mut(),
+            &high_scores,
+            &view,
+            actions.bindings(),
+        );
+    }
+}
+
+/// Feed the keyboard state to the game and react to whatever happened.
 #[allow(clippy::too_many_arguments)]
-fn place(
-    mut board: ResMut<Board>,
-    q_piece: Query<(Entity, &Piece), With<PlacePiece>>,
+fn update_game(
+    actions: Actions,
+    mut game: ResMut<Tetris>,
+    mut recording: ResMut<Recording>,
+    mut suppressed: ResMut<SuppressedInput>,
+    mut clear_message: ResMut<ClearMessage>,
     mut commands: Commands,
-    mut score: ResMut<Scoring>,
-    mut lines_to_clear: Local<BTreeSet<usize>>,
     server: Res<AssetServer>,
     settings: Res<Settings>,
 ) {
-    for (entity, piece) in &q_piece {
-        for p in piece.grid_points() {
-            if p.y >= BOARD_HEIGHT as i32 {
-                commands.set_state(GameState::GameOver);
-                return;
+    let held = read_input(&actions).to_bits();
+    suppressed.0 &= held;
+    let input = Input::from_bits(held & !suppressed.0);
+    recording.0.record(input);
+
+    for event in game.update(input) {
+        match event {
+            GameEvent::Locked(clear) => {
+                clear_message.show(&clear);
+
+                let sound: Handle<AudioSource> = match clear.lines {
+                    0 => server.load("place.wav"),
+                    _ if clear.perfect_clear => server.load("perfect_clear.wav"),
+                    1 => server.load("1line.wav"),
+                    4 => server.load("tetris.wav"),
+                    _ => server.load("2_3_lines.wav"),
+                };
+                commands.spawn((
+                    AudioPlayer::new(sound),
+                    PlaybackSettings::ONCE.with_volume(Volume::new(settings.sound_volume)),
+                ));
             }
-            lines_to_clear.insert(p.y as usize);
-            let i = p.as_index(BOARD_SIZE);
-            board.state[i] = piece.piece_id;
+            GameEvent::GameOver => commands.set_state(GameState::GameOver),
+            GameEvent::Finished => commands.set_state(GameState::Finished),
         }
-        // audio.play(sfx.place.clone());
-        commands.entity(entity).despawn();
-        commands.send_event(NextPiece);
-        commands.spawn((
-            AudioPlayer::new(server.load("place.wav")),
-            PlaybackSettings::ONCE.with_volume(Volume::new(settings.sound_volume)),
-        ));
-    }
-
-    let mut count = 0;
-    // Lines must be cleared in reverse order
-    for line in lines_to_clear.iter().rev() {
-        if board.is_line_filled(*line) {
-            board.clear_line(*line);
-            count += 1;
+    }
+}
+
+/// The game buttons currently held.
+fn read_input(actions: &Actions) -> Input {
+    let held = |action| actions.held(action);
+    Input {
+        left: held(GameAction::MoveLeft),
+        right: held(GameAction::MoveRight),
+        soft_drop: held(GameAction::SoftDrop),
+        hard_drop: held(GameAction::HardDrop),
+        rotate_cw: held(GameAction::RotateCW),
+        rotate_ccw: held(GameAction::RotateCCW),
+        rotate_180: held(GameAction::Rotate180),
+        hold: held(GameAction::Hold),
+    }
+}
+
+/// Pause on request, or when the window loses focus or is hidden, ie when
+/// switching to another browser tab.
+fn pause_game(
+    actions: Actions,
+    game: Res<Tetris>,
+    mut focused: EventReader<WindowFocused>,
+    mut occluded: EventReader<WindowOccluded>,
+    mut commands: Commands,
+) {
+    let lost_focus = focused.read().any(|e| !e.focused);
+    let hidden = occluded.read().any(|e| e.occluded);
+    // The game ended earlier this frame, pausing would replace the move to the
+    // end screen
+    if game.is_over() {
+        return;
+    }
+    if actions.just_pressed(GameAction::Pause) || lost_focus || hidden {
+        commands.set_state(GameState::Paused);
+    }
+}
+
+/// Freeze the music and hide everything that could be studied while the
+/// game is stopped.
+fn pause(
+    mut q_terms: Query<(&mut Terminal, Has<BoardTerminal>), Without<ScoreTerminal>>,
+    q_music: Query<&AudioSink, With<Music>>,
+    actions: Actions,
+    mut cursor: ResMut<PauseCursor>,
+) {
+    q_music.iter().for_each(AudioSink::pause);
+
+    cursor.0 = PauseOption::default();
+    for (mut term, is_board) in &mut q_terms {
+        term.clear();
+        if is_board {
+            term.resize([BOARD_WIDTH + 20, BOARD_HEIGHT]);
+            draw_pause(&mut term, actions.bindings(), cursor.0);
         }
     }
-    lines_to_clear.clear();
+}
 
-    if count != 0 {
-        score.line_clears(count);
-        let sound: Handle<AudioSource> = match count {
-            1 => server.load("1line.wav"),
-            4 => server.load("tetris.wav"),
-            _ => server.load("2_3_lines.wav"),
+fn draw_pause(term: &mut Terminal, bindings: &Bindings, selected: PauseOption) {
+    term.clear();
+    term.put_string([0, 4].pivot(Pivot::Center), "Paused".fg(color::RED));
+    for (y, option) in [2, 1, 0].into_iter().zip(PauseOption::ALL) {
+        let color = if option == selected {
+            color::YELLOW
+        } else {
+            color::WHITE
         };
-        commands.spawn((
-            AudioPlayer::new(sound),
-            PlaybackSettings::ONCE.with_volume(Volume::new(settings.sound_volume)),
-        ));
+        term.put_string([0, y].pivot(Pivot::Center), option.name().fg(color));
+    }
+    term.put_string(
+        [0, -3].pivot(Pivot::Center),
+        format!("Select: {}", bindings.describe(GameAction::Confirm)),
+    );
+    term.put_string(
+        [0, -4].pivot(Pivot::Center),
+        format!("Resume: {}", bindings.describe(GameAction::Pause)),
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pause_input(
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    actions: Actions,
+    mut cursor: ResMut<PauseCursor>,
+    mut game: ResMut<Tetris>,
+    mut recording: ResMut<Recording>,
+    mut suppressed: ResMut<SuppressedInput>,
+    options: Res<GameOptions>,
+    settings: Res<Settings>,
+    mut clear_message: ResMut<ClearMessage>,
+    q_music: Query<Entity, With<Music>>,
+    server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    let option = match actions.pause_or_confirm() {
+        Some(GameAction::Pause) => PauseOption::Resume,
+        Some(_) => cursor.0,
+        None => {
+            let step = actions.menu_y();
+            if step != 0 {
+                let all = PauseOption::ALL;
+                let i = all.iter().position(|o| *o == cursor.0).unwrap();
+                cursor.0 = all[(i as isize + step).rem_euclid(all.len() as isize) as usize];
+                draw_pause(&mut q_board_term.single_mut(), actions.bindings(), cursor.0);
+            }
+            return;
+        }
+    };
+
+    match option {
+        PauseOption::Resume => {
+            // Whatever was pressed to resume shouldn't also move the piece
+            suppressed.0 = read_input(&actions).to_bits();
+            commands.set_state(GameState::Playing);
+        }
+        PauseOption::Restart => {
+            recording.0 = options.new_replay(&settings);
+            *game = Tetris(recording.0.start());
+            *clear_message = ClearMessage::default();
+            for entity in &q_music {
+                commands.entity(entity).despawn();
+            }
+            start_music(&mut commands, &server, &settings);
+            suppressed.0 = read_input(&actions).to_bits();
+            commands.set_state(GameState::Playing);
+        }
+        PauseOption::Quit => {
+            for entity in &q_music {
+                commands.entity(entity).despawn();
+            }
+            commands.set_state(GameState::Title);
+        }
+    }
+}
+
+fn unpause(
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    q_music: Query<&AudioSink, With<Music>>,
+) {
+    q_music.iter().for_each(AudioSink::play);
+    let mut term = q_board_term.single_mut();
+    term.clear();
+    term.resize([BOARD_WIDTH, BOARD_HEIGHT]);
+}
+
+/// Keep the recording of the game that just ended so it can be watched.
+fn save_replay(recording: Res<Recording>, mut last_replay: ResMut<LastReplay>) {
+    last_replay.0 = Some(recording.0.clone());
+    last_replay.save();
+}
+
+fn watch_replay(
+    mut commands: Commands,
+    last_replay: Res<LastReplay>,
+    mut game: ResMut<Tetris>,
+    mut clear_message: ResMut<ClearMessage>,
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+) {
+    let Some(replay) = last_replay.0.clone() else {
+        commands.set_state(GameState::Title);
+        return;
+    };
+    *game = Tetris(replay.start());
+    *clear_message = ClearMessage::default();
+    commands.insert_resource(ReplayViewer::new(replay));
+
+    let mut term = q_board_term.single_mut();
+    term.clear();
+    term.resize([BOARD_WIDTH, BOARD_HEIGHT]);
+
+    // Hangs below the score panel, there's no room between it and the hold piece
+    commands.spawn((
+        Terminal::new([12, 4]),
+        ReplayTerminal,
+        TerminalMeshPivot::TopLeft,
+        SetTerminalGridPosition(IVec2::new(-13, 0)),
+    ));
+}
+
+fn stop_replay(mut commands: Commands, q_term: Query<Entity, With<ReplayTerminal>>) {
+    for entity in &q_term {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<ReplayViewer>();
+}
+
+/// Play the replay at the chosen speed, with controls to pause and seek.
+fn replay_input(
+    actions: Actions,
+    time: Res<Time>,
+    mut viewer: ResMut<ReplayViewer>,
+    mut game: ResMut<Tetris>,
+    mut clear_message: ResMut<ClearMessage>,
+    mut commands: Commands,
+) {
+    match actions.pause_or_confirm() {
+        Some(GameAction::Pause) => {
+            commands.set_state(GameState::Title);
+            return;
+        }
+        Some(_) => {
+            if viewer.finished() {
+                viewer.seek(&mut game.0, 0.0);
+            }
+            viewer.playing = !viewer.playing;
+        }
+        None => {}
+    }
+
+    // Up is faster
+    let speed = viewer.speed as isize - actions.menu_y();
+    viewer.speed = speed.clamp(0, SPEEDS.len() as isize - 1) as usize;
+
+    let seek = actions.menu_x();
+    if seek != 0 {
+        let time = viewer.played() + seek as f32 * SEEK_TIME;
+        viewer.seek(&mut game.0, time);
+        *clear_message = ClearMessage::default();
+    }
+
+    if viewer.playing {
+        viewer.clock += time.delta_secs() * SPEEDS[viewer.speed];
+        for event in viewer.advance(&mut game.0) {
+            if let GameEvent::Locked(clear) = event {
+                clear_message.show(&clear);
+            }
+        }
+    }
+}
+
+fn draw_replay(
+    viewer: Res<ReplayViewer>,
+    bindings: Res<Bindings>,
+    mut q_term: Query<&mut Terminal, With<ReplayTerminal>>,
+) {
+    let Ok(mut term) = q_term.get_single_mut() else {
+        return;
+    };
+    term.clear();
+
+    let status = if viewer.playing { "Pause" } else { "Play" };
+    let play_keys = format!("{}: {status}", bindings.describe(GameAction::Confirm));
+    term.put_string(
+        [0, 0],
+        format!("Replay {}x", SPEEDS[viewer.speed]).fg(color::RED),
+    );
+    term.put_string([0, 1], format_time(viewer.played()));
+    term.put_string([0, 2], play_keys.chars().take(12).collect::<String>());
+    term.put_string([0, 3], "Seek←→ Spd↑↓");
+}
+
+fn tick_clear_message(time: Res<Time>, mut clear_message: ResMut<ClearMessage>) {
+    if clear_message.text.is_none() && !clear_message.perfect_clear {
+        return;
+    }
+    if clear_message.timer.tick(time.delta()).just_finished() {
+        clear_message.text = None;
+        clear_message.perfect_clear = false;
     }
 }
 
 fn draw_board(
     mut q_term: Query<&mut Terminal, With<BoardTerminal>>,
-    q_pieces: Query<&Piece, With<Active>>,
-    board: Res<Board>,
+    game: Res<Tetris>,
+    clear_message: Res<ClearMessage>,
 ) {
-    if q_term.is_empty() || q_pieces.is_empty() {
+    if q_term.is_empty() {
         return;
     }
 
     let mut term = q_term.single_mut();
     term.clear();
 
-    for piece in &q_pieces {
-        // Draw drop ghost
-        let (drop_point, _, _) = try_drop(piece.pos, &piece.points, &board, 30.);
-        for pos in grid_points(&piece.points) {
-            let pos = drop_point.floor().as_ivec2() + pos;
+    // Draw drop ghost
+    if let Some(ghost) = game.ghost() {
+        for pos in ghost.grid_points() {
             if term.bounds().contains_point(pos) {
-                let mut col = piece.color;
+                let mut col = PIECE_COLORS[ghost.piece_id];
                 col.set_alpha(DROP_GHOST_ALPHA);
                 term.put_char(pos, DROP_GHOST_GLYPH).fg(col);
             }
         }
+    }
 
-        // Draw actual piece
+    // Draw actual piece
+    if let Some(piece) = game.active() {
         for pos in piece.grid_points() {
             if term.bounds().contains_point(pos) {
-                term.put_char(pos, PIECE_GLYPH).fg(piece.color);
+                term.put_char(pos, PIECE_GLYPH)
+                    .fg(PIECE_COLORS[piece.piece_id]);
             }
         }
     }
 
-    for (i, tile_index) in board
-        .state
+    for (i, tile_index) in game
+        .board()
+        .visible()
         .iter()
         .enumerate()
-        .filter(|(_, p)| **p < EMPTY_SQUARE)
+        .filter(|(_, p)| **p != EMPTY_SQUARE)
     {
-        let color = PIECES[*tile_index].color;
+        let color = match *tile_index {
+            GARBAGE_SQUARE => GARBAGE_COLOR,
+            piece_id => PIECE_COLORS[piece_id],
+        };
         let xy = term.index_to_tile(i);
         term.put_char(xy, BOARD_GLYPH).fg(color);
     }
+
+    // Filled lines flash white while dissolving from the middle out
+    if let Some((lines, progress)) = game.clearing() {
+        let half = BOARD_WIDTH as f32 / 2.0;
+        let flash = ((progress * LINE_CLEAR_FLASHES * 2.0) as usize).is_multiple_of(2);
+        // Lines in the buffer above the board can fill too, but aren't drawn
+        for line in lines.iter().filter(|line| **line < BOARD_HEIGHT) {
+            for x in 0..BOARD_WIDTH {
+                let pos = IVec2::new(x as i32, *line as i32);
+                if (x as f32 + 0.5 - half).abs() < progress * half {
+                    term.put_char(pos, ' ');
+                } else if flash {
+                    term.put_char(pos, BOARD_GLYPH).fg(color::WHITE);
+                }
+            }
+        }
+    }
+
+    if clear_message.perfect_clear {
+        term.put_string([0, 0].pivot(Pivot::Center), "ALL CLEAR".fg(color::YELLOW));
+    }
 }
 
-fn draw_score(mut q_term: Query<&mut Terminal, With<ScoreTerminal>>, score: Res<Scoring>) {
+fn draw_score(
+    mut q_term: Query<&mut Terminal, With<ScoreTerminal>>,
+    game: Res<Tetris>,
+    clear_message: Res<ClearMessage>,
+    high_scores: Res<HighScores>,
+) {
     if q_term.is_empty() {
         return;
     }
 
-    if score.is_changed() {
-        let mut term = q_term.single_mut();
+    let mut term = q_term.single_mut();
 
-        term.clear();
-        term.put_string([1, 0], "Score:");
-        term.put_string([2, 1], score.score().to_string());
+    term.clear();
+    match game.mode() {
+        GameMode::Marathon | GameMode::Ultra => draw_marathon(&mut term, &game),
+        GameMode::Sprint => draw_sprint(&mut term, &game, high_scores.best(game.mode())),
+        GameMode::Cheese(_) => draw_cheese(&mut term, &game),
+    }
+    // Two rows at the bottom, longer names wrap onto the second
+    if let Some(text) = &clear_message.text {
+        term.put_string([1, 10], text.as_str().fg(color::YELLOW));
+    }
+}
+
+fn draw_marathon(term: &mut Terminal, game: &Game) {
+    let score = game.scoring();
+    term.put_string([1, 0], "Score:");
+    term.put_string([2, 1], score.score().to_string());
+    if let Some(limit) = game.mode().time_limit() {
+        term.put_string([1, 2], "Time:");
+        term.put_string([2, 3], format_time(limit - game.elapsed()));
+    } else {
         term.put_string([1, 2], "Level:");
         term.put_string([2, 3], score.level().to_string());
-        term.put_string([1, 4], "Lines:");
-        term.put_string([2, 5], score.lines().to_string());
+    }
+    term.put_string([1, 4], "Lines:");
+    term.put_string([2, 5], score.lines().to_string());
+    if score.combo() > 0 {
+        term.put_string([1, 6], format!("Combo: {}", score.combo()));
+    }
+    if score.back_to_back() > 0 {
+        term.put_string([1, 7], format!("B2B: {}", score.back_to_back()));
+    }
+}
+
+/// Room for the header and three rows per piece, with a gap after the first.
+fn next_queue_size(count: usize) -> [usize; 2] {
+    [7, 2 + count * 3]
+}
+
+fn draw_sprint(term: &mut Terminal, game: &Game, best: Option<&HighScore>) {
+    term.put_string([1, 0], "Time:");
+    term.put_string([2, 1], format_time(game.elapsed()));
+    term.put_string([1, 2], "Lines:");
+    term.put_string(
+        [2, 3],
+        format!(
+            "{}/{}",
+            game.scoring().lines().min(SPRINT_LINES),
+            SPRINT_LINES
+        ),
+    );
+    term.put_string([1, 4], "Splits:");
+    for (i, split) in game.splits().iter().enumerate() {
+        let lines = (i + 1) * SPLIT_LINES;
+        term.put_string(
+            [1, 5 + i as i32],
+            format!("{lines} {}", format_time(*split)),
+        );
+    }
+
+    // Compare the latest split against the same split of the personal best
+    let i = game.splits().len();
+    if let Some(best) = best
+        && i > 0
+        && let Some(best_split) = best.splits.get(i - 1)
+    {
+        let diff = game.splits()[i - 1] - best_split;
+        term.put_string([1, 9], format_diff(diff));
     }
 }
 
-fn draw_next(bag: Res<ShuffleBag>, mut q_term: Query<&mut Terminal, With<NextPieceTerminal>>) {
+fn draw_cheese(term: &mut Terminal, game: &Game) {
+    term.put_string([1, 0], "Time:");
+    term.put_string([2, 1], format_time(game.elapsed()));
+    term.put_string([1, 2], "Garbage:");
+    term.put_string([2, 3], game.garbage_left().to_string());
+    term.put_string([1, 4], "Lines:");
+    term.put_string([2, 5], game.scoring().lines().to_string());
+}
+
+/// Format seconds as minutes, seconds and milliseconds, ie "1:05.250".
+fn format_time(secs: f32) -> String {
+    let millis = (secs * 1000.0) as u64;
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Format the difference to a personal best, green when ahead and red when
+/// behind.
+fn format_diff(diff: f32) -> DecoratedString<String> {
+    if diff <= 0.0 {
+        format!("-{:.3}", -diff).fg(color::GREEN)
+    } else {
+        format!("+{diff:.3}").fg(color::RED)
+    }
+}
+
+fn draw_next(
+    mut game: ResMut<Tetris>,
+    settings: Res<Settings>,
+    mut q_term: Query<&mut Terminal, With<NextPieceTerminal>>,
+) {
     if q_term.is_empty() {
         return;
     }
 
-    if bag.is_changed() {
-        let mut term = q_term.single_mut();
-        term.clear();
+    let mut term = q_term.single_mut();
+    let size = next_queue_size(settings.preview_count);
+    if term.size() != UVec2::new(size[0] as u32, size[1] as u32) {
+        term.resize(size);
+    }
+    term.clear();
+
+    term.put_string([1, 0].pivot(Pivot::TopLeft), "Next:");
+    let top = size[1] as i32 - 1;
+    for (i, piece) in game.next_queue(settings.preview_count).iter().enumerate() {
+        // Only the piece coming up next is drawn at full brightness
+        let (y, alpha) = match i {
+            0 => (top - 2, 1.0),
+            _ => (top - 3 - i as i32 * 3, 0.5),
+        };
+        let mut color = PIECE_COLORS[piece.piece_id];
+        color.set_alpha(alpha);
+        for p in piece.grid_points() {
+            let p = IVec2::new(3, y) + p;
+            term.put_char(p, PIECE_GLYPH).fg(color);
+        }
+    }
+}
 
-        term.put_string([1, 0].pivot(Pivot::TopLeft), "Next:");
-        let piece = bag.peek();
+fn draw_hold(game: Res<Tetris>, mut q_term: Query<&mut Terminal, With<HoldPieceTerminal>>) {
+    if q_term.is_empty() {
+        return;
+    }
+
+    let mut term = q_term.single_mut();
+    term.clear();
+
+    term.put_string([1, 0].pivot(Pivot::TopLeft), "Hold:");
+    if let Some(piece) = game.hold() {
+        // Dim the held piece while it can't be swapped
+        let mut color = PIECE_COLORS[piece.piece_id];
+        if game.hold_used() {
+            color.set_alpha(0.3);
+        }
         for p in piece.grid_points() {
             let p = IVec2::new(3, 2) + p;
-            term.put_char(p, PIECE_GLYPH).fg(piece.color);
+            term.put_char(p, PIECE_GLYPH).fg(color);
         }
     }
 }
 
 fn game_over(
     mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
-    q_pieces: Query<Entity, With<Piece>>,
-    score: Res<Scoring>,
+    game: Res<Tetris>,
+    bindings: Res<Bindings>,
     mut commands: Commands,
     server: Res<AssetServer>,
     q_music: Query<Entity, With<Music>>,
     settings: Res<Settings>,
 ) {
-    for entity in &q_pieces {
-        commands.entity(entity).despawn();
-    }
     let mut term = q_board_term.single_mut();
 
     term.clear();
@@ -495,9 +1558,19 @@ fn game_over(
     term.put_string([0, 2].pivot(Pivot::Center), "Final Score: ");
     term.put_string(
         [0, 0].pivot(Pivot::Center),
-        score.score().to_string().fg(color::YELLOW),
+        game.scoring().score().to_string().fg(color::YELLOW),
+    );
+    term.put_string(
+        [0, -2].pivot(Pivot::Center),
+        format!("Seed: {}", game.seed()),
+    );
+    term.put_string(
+        [0, -4].pivot(Pivot::Center),
+        format!(
+            "Press {} to restart",
+            bindings.describe(GameAction::Confirm)
+        ),
     );
-    term.put_string([0, -2].pivot(Pivot::Center), "Press Space to restart");
 
     for entity in &q_music {
         commands.entity(entity).despawn();
@@ -508,68 +1581,105 @@ fn game_over(
     ));
 }
 
-fn get_tile(board: &Board, xy: IVec2) -> Option<usize> {
-    if in_bounds(xy) {
-        Some(board.state[xy.as_index(BOARD_SIZE)])
-    } else {
-        None
+#[allow(clippy::too_many_arguments)]
+fn finished(
+    mut q_board_term: Query<&mut Terminal, With<BoardTerminal>>,
+    game: Res<Tetris>,
+    high_scores: Res<HighScores>,
+    bindings: Res<Bindings>,
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    q_music: Query<Entity, With<Music>>,
+    settings: Res<Settings>,
+) {
+    let mut term = q_board_term.single_mut();
+
+    term.clear();
+    term.resize([BOARD_WIDTH + 20, BOARD_HEIGHT]);
+
+    // The table isn't updated until the player enters their initials, so the
+    // top entry is still the best from before this game
+    let best = high_scores.best(game.mode());
+    match game.mode() {
+        GameMode::Sprint | GameMode::Cheese(_) => {
+            let previous = keeps_scores(game.mode()).then(|| best.map(|b| b.time));
+            draw_time_result(&mut term, game.elapsed(), previous);
+        }
+        GameMode::Ultra => draw_ultra_result(&mut term, &game, best.map(|b| b.score)),
+        GameMode::Marathon => {}
     }
-}
 
-/// Pieces spawn above the board so the points above the board are valid for
-/// movement, but not rendering
-fn in_stage(xy: IVec2) -> bool {
-    let [x, y] = xy.to_array();
-    x >= 0 && x < BOARD_WIDTH as i32 && y >= 0
-}
+    term.put_string(
+        [0, -2].pivot(Pivot::Center),
+        format!("Seed: {}", game.seed()),
+    );
+    term.put_string(
+        [0, -4].pivot(Pivot::Center),
+        format!(
+            "Press {} to restart",
+            bindings.describe(GameAction::Confirm)
+        ),
+    );
 
-fn in_bounds(xy: IVec2) -> bool {
-    let [x, y] = xy.to_array();
-    x >= 0 && x < BOARD_WIDTH as i32 && y >= 0 && y < BOARD_HEIGHT as i32
+    for entity in &q_music {
+        commands.entity(entity).despawn();
+    }
+    commands.spawn((
+        AudioPlayer::new(server.load("tetris.wav")),
+        PlaybackSettings::ONCE.with_volume(Volume::new(settings.sound_volume)),
+    ));
 }
 
-fn piece_is_visible(piece: &Piece) -> bool {
-    piece
-        .points
-        .iter()
-        .any(|p| in_stage(piece.pos.as_ivec2() + p.as_ivec2()))
-}
-
-/// Try to move a block down by the given amount.
-///
-/// Returns (position after move, whether or not we hit something, and number of lines moved)
-fn try_drop(pos: Vec2, points: &[Vec2], board: &Board, dist: f32) -> (Vec2, bool, usize) {
-    let curr_grid = pos.floor().as_ivec2();
-    let mut next = pos - Vec2::new(0., dist);
-    let next_grid = next.floor().as_ivec2();
-    let diff = curr_grid.y - next_grid.y;
-
-    let mut hit = false;
-    for y in 1..=diff {
-        let movement = IVec2::new(0, -y);
-        if !can_move(board, pos, grid_points(points), movement) {
-            next.y = pos.y - (y as f32 - 1.0);
-            hit = true;
-            break;
+/// Show the final time of a race against the previous best. `previous` is
+/// `None` for a race whose results aren't kept, so there's no best to beat.
+fn draw_time_result(term: &mut Terminal, time: f32, previous: Option<Option<f32>>) {
+    term.put_string([0, 5].pivot(Pivot::Center), "Finished!".fg(color::GREEN));
+    term.put_string([0, 4].pivot(Pivot::Center), "Final Time: ");
+    term.put_string(
+        [0, 2].pivot(Pivot::Center),
+        format_time(time).fg(color::YELLOW),
+    );
+
+    let Some(previous) = previous else {
+        return;
+    };
+    if let Some(previous) = previous {
+        term.put_string([0, 0].pivot(Pivot::Center), format_diff(time - previous));
+    }
+    match previous {
+        Some(previous) if previous <= time => {
+            term.put_string(
+                [0, 1].pivot(Pivot::Center),
+                format!("Best: {}", format_time(previous)),
+            );
+        }
+        _ => {
+            term.put_string(
+                [0, 1].pivot(Pivot::Center),
+                "New Personal Best!".fg(color::GREEN),
+            );
         }
     }
-    (next, hit, diff as usize)
 }
 
-fn can_move(
-    board: &Board,
-    pos: Vec2,
-    points: impl Iterator<Item = IVec2>,
-    movement: impl GridPoint,
-) -> bool {
-    let movement = movement.to_ivec2();
-    let pos = pos.floor().as_ivec2();
-
-    points
-        .map(|p| pos + p + movement)
-        .all(|p| get_tile(board, p).map_or(in_stage(p), |tile| tile == EMPTY_SQUARE))
-}
+fn draw_ultra_result(term: &mut Terminal, game: &Game, previous: Option<usize>) {
+    let score = game.scoring().score();
+    term.put_string([0, 5].pivot(Pivot::Center), "Time's Up!".fg(color::YELLOW));
+    term.put_string([0, 4].pivot(Pivot::Center), "Final Score: ");
+    term.put_string(
+        [0, 2].pivot(Pivot::Center),
+        score.to_string().fg(color::YELLOW),
+    );
 
-fn grid_points(points: &[Vec2]) -> impl Iterator<Item = IVec2> + '_ {
-    points.iter().map(|p| p.floor().as_ivec2())
+    match previous {
+        Some(previous) if previous >= score => {
+            term.put_string([0, 1].pivot(Pivot::Center), format!("Best: {previous}"));
+        }
+        _ => {
+            term.put_string(
+                [0, 1].pivot(Pivot::Center),
+                "New Personal Best!".fg(color::GREEN),
+            );
+        }
+    }
 }
@@ -0,0 +1,118 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use tetris_core::{Game, GameEvent, REPLAY_VERSION, Replay, TICK_RATE};
+
+use crate::storage;
+
+const REPLAY_FILE: &str = "replay.ron";
+/// Playback speeds the viewer can switch between
+pub const SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+/// Seconds skipped forward or back by each seek
+pub const SEEK_TIME: f32 = 5.0;
+
+/// The version on its own, read first so a replay from an older version can be
+/// rejected even if the rest of it no longer parses.
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+/// The recording of the game being played.
+#[derive(Resource)]
+pub struct Recording(pub Replay);
+
+/// The most recently finished game, saved between runs.
+#[derive(Default, Resource)]
+pub struct LastReplay(pub Option<Replay>);
+
+impl LastReplay {
+    pub fn load() -> Self {
+        let Some(contents) = storage::load(REPLAY_FILE) else {
+            return Self(None);
+        };
+        match ron::from_str::<Version>(&contents) {
+            Ok(Version { version }) if version == REPLAY_VERSION => {}
+            _ => {
+                warn!("Ignoring replay from an unsupported version");
+                return Self(None);
+            }
+        }
+        Self(storage::load_ron(REPLAY_FILE))
+    }
+
+    /// Saved without pretty printing, which would put every update on its own
+    /// line.
+    pub fn save(&self) {
+        let Some(replay) = &self.0 else {
+            return;
+        };
+        match ron::to_string(replay) {
+            Ok(s) => storage::save(REPLAY_FILE, &s),
+            Err(e) => warn!("Unable to serialize {REPLAY_FILE}: {e}"),
+        }
+    }
+}
+
+/// Where the replay being watched is up to.
+#[derive(Resource)]
+pub struct ReplayViewer {
+    pub replay: Replay,
+    /// The next recorded update to play
+    pub frame: usize,
+    /// Where playback should be in seconds. Runs ahead of what's been played by
+    /// less than an update.
+    pub clock: f32,
+    pub playing: bool,
+    /// Index into [SPEEDS]
+    pub speed: usize,
+}
+
+impl ReplayViewer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            frame: 0,
+            clock: 0.0,
+            playing: true,
+            speed: SPEEDS.iter().position(|s| *s == 1.0).unwrap(),
+        }
+    }
+
+    /// Seconds of the replay played so far.
+    pub fn played(&self) -> f32 {
+        self.frame as f32 / TICK_RATE as f32
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.replay.len()
+    }
+
+    /// Play recorded updates through the game until it catches up with the
+    /// clock.
+    pub fn advance(&mut self, game: &mut Game) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        let target = (self.clock * TICK_RATE as f32) as usize;
+        while self.frame < target
+            && let Some(input) = self.replay.frame(self.frame)
+        {
+            events.extend(game.update(input));
+            self.frame += 1;
+        }
+        if self.finished() {
+            self.playing = false;
+        }
+        events
+    }
+
+    /// Jump to a time in the replay. Going backwards replays the game from the
+    /// start.
+    pub fn seek(&mut self, game: &mut Game, time: f32) {
+        let time = time.clamp(0.0, self.replay.duration());
+        if time < self.played() {
+            *game = self.replay.start();
+            self.frame = 0;
+        }
+        self.clock = time;
+        self.advance(game);
+    }
+}
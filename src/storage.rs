@@ -0,0 +1,96 @@
+//! Small save files. Stored under the user's data directory on native builds
+//! and in the browser's `localStorage` on the web.
+
+use bevy::prelude::*;
+use serde::{Serialize, de::DeserializeOwned};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::path::PathBuf;
+
+    use bevy::prelude::*;
+
+    /// The platform's per user data directory, ie `~/.local/share` on Linux.
+    fn data_dir() -> Option<PathBuf> {
+        let env = |key| std::env::var_os(key).filter(|v| !v.is_empty());
+        let home = || env("HOME").map(PathBuf::from);
+        if cfg!(target_os = "windows") {
+            env("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            home().map(|h| h.join("Library/Application Support"))
+        } else {
+            env("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| home().map(|h| h.join(".local/share")))
+        }
+    }
+
+    fn path(name: &str) -> Option<PathBuf> {
+        data_dir().map(|dir| dir.join("bevy_ascii_tetris").join(name))
+    }
+
+    pub fn load(name: &str) -> Option<String> {
+        std::fs::read_to_string(path(name)?).ok()
+    }
+
+    pub fn save(name: &str, contents: &str) {
+        let Some(path) = path(name) else {
+            warn!("No data directory to save {name} to");
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, contents));
+        if let Err(e) = result {
+            warn!("Unable to save {}: {e}", path.display());
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use bevy::prelude::*;
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    fn key(name: &str) -> String {
+        format!("bevy_ascii_tetris/{name}")
+    }
+
+    pub fn load(name: &str) -> Option<String> {
+        local_storage()?.get_item(&key(name)).ok()?
+    }
+
+    pub fn save(name: &str, contents: &str) {
+        let saved = local_storage().map(|storage| storage.set_item(&key(name), contents));
+        if !matches!(saved, Some(Ok(()))) {
+            warn!("Unable to save {name} to local storage");
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{load, save};
+#[cfg(target_arch = "wasm32")]
+pub use web::{load, save};
+
+/// Load a value saved with [save_ron], or `None` if it's missing or unreadable.
+pub fn load_ron<T: DeserializeOwned>(name: &str) -> Option<T> {
+    match ron::from_str(&load(name)?) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Ignoring unreadable {name}: {e}");
+            None
+        }
+    }
+}
+
+pub fn save_ron<T: Serialize>(name: &str, value: &T) {
+    match ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()) {
+        Ok(s) => save(name, &s),
+        Err(e) => warn!("Unable to serialize {name}: {e}"),
+    }
+}
@@ -0,0 +1,107 @@
+use glam::IVec2;
+
+use crate::{BOARD_HEIGHT, BOARD_WIDTH, BUFFER_HEIGHT};
+
+pub const EMPTY_SQUARE: usize = crate::piece::PIECES.len();
+/// A block of garbage that didn't come from a piece
+pub const GARBAGE_SQUARE: usize = EMPTY_SQUARE + 1;
+/// Rows stored in the board, the visible ones and the buffer above them
+pub const TOTAL_HEIGHT: usize = BOARD_HEIGHT + BUFFER_HEIGHT;
+
+#[derive(Clone)]
+pub struct Board {
+    pub state: Vec<usize>,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self {
+            state: vec![EMPTY_SQUARE; BOARD_WIDTH * TOTAL_HEIGHT],
+        }
+    }
+}
+
+impl Board {
+    /// The tiles of the visible rows, without the buffer above them.
+    pub fn visible(&self) -> &[usize] {
+        &self.state[..BOARD_WIDTH * BOARD_HEIGHT]
+    }
+
+    pub fn is_line_filled(&self, line: usize) -> bool {
+        let i = line * BOARD_WIDTH;
+        self.state[i..i + BOARD_WIDTH]
+            .iter()
+            .all(|v| *v != EMPTY_SQUARE)
+    }
+
+    pub fn clear_line(&mut self, line: usize) {
+        let i = line * BOARD_WIDTH;
+        // Remove our line, add an empty one to the end
+        self.state.drain(i..i + BOARD_WIDTH).count();
+        self.state.extend([EMPTY_SQUARE; BOARD_WIDTH].iter());
+    }
+
+    pub fn line_contains(&self, line: usize, tile: usize) -> bool {
+        let i = line * BOARD_WIDTH;
+        self.state[i..i + BOARD_WIDTH].contains(&tile)
+    }
+
+    /// Insert a row at the bottom of the board, pushing everything above it up
+    /// by one line.
+    ///
+    /// Returns false if any blocks were pushed off the top of the board.
+    pub fn push_row(&mut self, row: [usize; BOARD_WIDTH]) -> bool {
+        let top = self.state.len() - BOARD_WIDTH;
+        let overflowed = self.state[top..].iter().any(|v| *v != EMPTY_SQUARE);
+        self.state.truncate(top);
+        self.state.splice(0..0, row);
+        !overflowed
+    }
+
+    pub fn reset(&mut self) {
+        self.state.fill(EMPTY_SQUARE);
+    }
+
+    pub fn get_tile(&self, xy: IVec2) -> Option<usize> {
+        if in_bounds(xy) {
+            Some(self.state[to_index(xy)])
+        } else {
+            None
+        }
+    }
+
+    pub fn set_tile(&mut self, xy: IVec2, tile: usize) {
+        self.state[to_index(xy)] = tile;
+    }
+
+    /// Whether the given points, offset by `pos` and `movement`, are all empty
+    /// and inside the stage.
+    pub fn can_move(
+        &self,
+        pos: IVec2,
+        mut points: impl Iterator<Item = IVec2>,
+        movement: IVec2,
+    ) -> bool {
+        points.all(|p| {
+            let p = pos + p + movement;
+            self.get_tile(p)
+                .map_or(in_stage(p), |tile| tile == EMPTY_SQUARE)
+        })
+    }
+}
+
+/// Pieces spawn above the board so the points above the board are valid for
+/// movement, but not rendering
+pub fn in_stage(xy: IVec2) -> bool {
+    let [x, y] = xy.to_array();
+    x >= 0 && x < BOARD_WIDTH as i32 && y >= 0
+}
+
+pub fn in_bounds(xy: IVec2) -> bool {
+    let [x, y] = xy.to_array();
+    x >= 0 && x < BOARD_WIDTH as i32 && y >= 0 && y < TOTAL_HEIGHT as i32
+}
+
+fn to_index(xy: IVec2) -> usize {
+    xy.y as usize * BOARD_WIDTH + xy.x as usize
+}
@@ -0,0 +1,1437 @@
+use std::collections::BTreeSet;
+
+use glam::{IVec2, Vec2};
+
+use crate::board::{Board, EMPTY_SQUARE, GARBAGE_SQUARE, TOTAL_HEIGHT, in_stage};
+use crate::garbage::Garbage;
+use crate::gravity::GravityCurve;
+use crate::mode::{GameMode, SPLIT_LINES};
+use crate::piece::{PIECES, Piece, Rotation, RotationState, T};
+use crate::randomizer::Randomizer;
+use crate::score::{self, Scoring, Spin};
+use crate::{BOARD_HEIGHT, BOARD_WIDTH, G, MAX_GRAVITY, TICK_RATE};
+
+/// Pieces spawn above the visible board
+pub const SPAWN_POS: IVec2 = IVec2::new(BOARD_WIDTH as i32 / 2, BOARD_HEIGHT as i32 + 2);
+/// Seconds a piece can rest on the stack before it locks
+pub const LOCK_DELAY: f32 = 0.5;
+/// How many moves or rotations can reset the lock delay before the piece locks
+pub const LOCK_MOVE_RESETS: usize = 15;
+/// Soft drop speed as a multiple of the current fall speed
+pub const SOFT_DROP_FACTOR: f32 = 20.0;
+/// Seconds a direction must be held before the piece starts auto shifting
+pub const DAS: f32 = 0.167;
+/// Seconds between each auto shift once DAS is charged. Zero moves the piece
+/// all the way to the wall instantly.
+pub const ARR: f32 = 0.033;
+/// Seconds between a piece locking and the next one appearing, also known as
+/// ARE
+pub const ENTRY_DELAY: f32 = 0.1;
+/// Seconds filled lines stay on the board before they're removed
+pub const LINE_CLEAR_DELAY: f32 = 0.3;
+
+/// Controls how the active piece responds to input. Times are in seconds, and
+/// are rounded to the nearest whole update.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Handling {
+    pub lock_delay: f32,
+    pub lock_move_resets: usize,
+    pub das: f32,
+    pub arr: f32,
+    pub soft_drop_factor: f32,
+    pub entry_delay: f32,
+    pub line_clear_delay: f32,
+    /// Whether locking a piece partly above the visible board ends the game.
+    /// Locking it entirely above the board always does.
+    pub partial_lock_out: bool,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Self {
+            lock_delay: LOCK_DELAY,
+            lock_move_resets: LOCK_MOVE_RESETS,
+            das: DAS,
+            arr: ARR,
+            soft_drop_factor: SOFT_DROP_FACTOR,
+            entry_delay: ENTRY_DELAY,
+            line_clear_delay: LINE_CLEAR_DELAY,
+            partial_lock_out: false,
+        }
+    }
+}
+
+/// Which game buttons are held down during an update. Presses are detected by
+/// comparing against the previous update.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    pub left: bool,
+    pub right: bool,
+    pub soft_drop: bool,
+    pub hard_drop: bool,
+    pub rotate_cw: bool,
+    pub rotate_ccw: bool,
+    pub rotate_180: bool,
+    pub hold: bool,
+}
+
+impl Input {
+    /// The buttons packed into a byte, one bit each.
+    pub fn to_bits(&self) -> u8 {
+        [
+            self.left,
+            self.right,
+            self.soft_drop,
+            self.hard_drop,
+            self.rotate_cw,
+            self.rotate_ccw,
+            self.rotate_180,
+            self.hold,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, held)| bits | (*held as u8) << i)
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        let held = |i: u8| bits & (1 << i) != 0;
+        Input {
+            left: held(0),
+            right: held(1),
+            soft_drop: held(2),
+            hard_drop: held(3),
+            rotate_cw: held(4),
+            rotate_ccw: held(5),
+            rotate_180: held(6),
+            hold: held(7),
+        }
+    }
+
+    /// Buttons that are held now but weren't held in `previous`.
+    fn pressed(&self, previous: &Input) -> Input {
+        Input {
+            left: self.left && !previous.left,
+            right: self.right && !previous.right,
+            soft_drop: self.soft_drop && !previous.soft_drop,
+            hard_drop: self.hard_drop && !previous.hard_drop,
+            rotate_cw: self.rotate_cw && !previous.rotate_cw,
+            rotate_ccw: self.rotate_ccw && !previous.rotate_ccw,
+            rotate_180: self.rotate_180 && !previous.rotate_180,
+            hold: self.hold && !previous.hold,
+        }
+    }
+}
+
+/// Something that happened during an update that a frontend may want to react to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameEvent {
+    /// The active piece was locked into the board.
+    Locked(Clear),
+    /// The stack topped out. A piece spawned overlapping the stack (block out),
+    /// locked above the visible board (lock out), or garbage pushed blocks off
+    /// the top of the buffer.
+    GameOver,
+    /// The goal of the game mode was reached, or its time ran out.
+    Finished,
+}
+
+/// The result of locking a piece into the board.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Clear {
+    pub lines: usize,
+    pub spin: Spin,
+    pub perfect_clear: bool,
+}
+
+impl Clear {
+    /// The display name of the clear, ie "T-SPIN DOUBLE".
+    pub fn name(&self) -> Option<String> {
+        score::clear_name(self.lines, self.spin)
+    }
+}
+
+enum DropType {
+    Normal,
+    Soft,
+    Hard,
+}
+
+/// Tracks how long the active piece has been resting on the stack and how many
+/// times moving or rotating has reset that timer.
+struct LockDelay {
+    /// Updates spent resting on the stack
+    timer: u32,
+    resets: usize,
+    lowest_y: i32,
+}
+
+impl Default for LockDelay {
+    fn default() -> Self {
+        Self {
+            timer: 0,
+            resets: 0,
+            lowest_y: i32::MAX,
+        }
+    }
+}
+
+/// What the game is doing between one piece locking and the next appearing.
+#[derive(Debug, Default)]
+enum Phase {
+    /// A piece is in play, or the next one can spawn.
+    #[default]
+    Falling,
+    /// Filled lines are still on the board, waiting to be removed.
+    LineClear {
+        /// The filled lines, bottom first
+        lines: Vec<usize>,
+        /// Updates left before they're removed
+        ticks: u32,
+    },
+    /// Waiting for the next piece to appear.
+    Entry {
+        /// Updates left before it spawns
+        ticks: u32,
+    },
+}
+
+/// Delayed auto shift state for the currently held movement direction.
+#[derive(Default)]
+struct AutoShift {
+    direction: i32,
+    /// Updates the direction has been held
+    held: u32,
+    moved: i32,
+}
+
+/// A single game from the first piece to game over.
+pub struct Game {
+    mode: GameMode,
+    gravity: GravityCurve,
+    board: Board,
+    randomizer: Box<dyn Randomizer>,
+    scoring: Scoring,
+    handling: Handling,
+    active: Option<Piece>,
+    lock: LockDelay,
+    /// The wall kick used by the last successful action on the active piece,
+    /// or `None` if the last action wasn't a rotation. Used for T-spin detection.
+    last_rotation: Option<usize>,
+    hold: Option<Piece>,
+    hold_used: bool,
+    shift: AutoShift,
+    phase: Phase,
+    /// Progress toward moving the active piece down another row, in fractions
+    /// of [G]
+    fall: u32,
+    prev_input: Input,
+    over: bool,
+    /// Updates played so far
+    ticks: u32,
+    /// The elapsed time each time another [SPLIT_LINES] lines were cleared
+    splits: Vec<f32>,
+    /// Rows waiting to rise from the bottom in a cheese race
+    garbage: Option<Garbage>,
+}
+
+impl Game {
+    /// Start a game dealing pieces from the given randomizer. Two games with the
+    /// same kind of randomizer and seed are dealt the same pieces.
+    pub fn new(
+        mode: GameMode,
+        gravity: GravityCurve,
+        start_level: usize,
+        handling: Handling,
+        randomizer: Box<dyn Randomizer>,
+    ) -> Self {
+        let garbage = match mode {
+            GameMode::Cheese(cheese) => Some(Garbage::new(cheese, randomizer.seed())),
+            _ => None,
+        };
+        let mut game = Self {
+            mode,
+            gravity,
+            board: Board::default(),
+            randomizer,
+            scoring: Scoring::new(start_level),
+            handling,
+            active: None,
+            lock: LockDelay::default(),
+            last_rotation: None,
+            hold: None,
+            hold_used: false,
+            shift: AutoShift::default(),
+            phase: Phase::default(),
+            fall: 0,
+            prev_input: Input::default(),
+            over: false,
+            ticks: 0,
+            splits: Vec::new(),
+            garbage,
+        };
+        game.raise_garbage();
+        game
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    pub fn gravity(&self) -> GravityCurve {
+        self.gravity
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.randomizer.seed()
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn scoring(&self) -> &Scoring {
+        &self.scoring
+    }
+
+    pub fn active(&self) -> Option<&Piece> {
+        self.active.as_ref()
+    }
+
+    /// The active piece moved to where it would land if hard dropped.
+    pub fn ghost(&self) -> Option<Piece> {
+        let mut ghost = self.active.clone()?;
+        drop_piece(&self.board, &mut ghost, u32::MAX);
+        Some(ghost)
+    }
+
+    /// The next `n` pieces that will be dealt, in order.
+    pub fn next_queue(&mut self, n: usize) -> Vec<Piece> {
+        self.randomizer.peek_n(n)
+    }
+
+    pub fn hold(&self) -> Option<&Piece> {
+        self.hold.as_ref()
+    }
+
+    /// Whether the hold slot has already been used for the current drop.
+    pub fn hold_used(&self) -> bool {
+        self.hold_used
+    }
+
+    /// Filled lines waiting to be removed, bottom first, and how far through
+    /// the line clear delay the game is, from 0 to 1. Lines in the buffer above
+    /// the visible board are included.
+    pub fn clearing(&self) -> Option<(&[usize], f32)> {
+        let Phase::LineClear { lines, ticks } = &self.phase else {
+            return None;
+        };
+        let total = to_ticks(self.handling.line_clear_delay).max(1);
+        Some((lines, 1.0 - *ticks as f32 / total as f32))
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    /// Seconds played so far.
+    pub fn elapsed(&self) -> f32 {
+        self.ticks as f32 / TICK_RATE as f32
+    }
+
+    /// Split times in seconds, one for every [SPLIT_LINES] lines cleared in a
+    /// mode with a line goal.
+    pub fn splits(&self) -> &[f32] {
+        &self.splits
+    }
+
+    /// Garbage rows left to clear in a cheese race, including those that haven't
+    /// risen onto the board yet.
+    pub fn garbage_left(&self) -> usize {
+        match &self.garbage {
+            Some(garbage) => self.garbage_on_board() + garbage.remaining(),
+            None => 0,
+        }
+    }
+
+    /// Advance the game by a single update, 1/[TICK_RATE] of a second, with the
+    /// given buttons held.
+    pub fn update(&mut self, input: Input) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        if self.over {
+            return events;
+        }
+        self.ticks += 1;
+        if let Some(limit) = self.mode.time_limit()
+            && self.ticks >= to_ticks(limit)
+        {
+            self.ticks = to_ticks(limit);
+            self.over = true;
+            events.push(GameEvent::Finished);
+            return events;
+        }
+
+        let pressed = input.pressed(&self.prev_input);
+        self.prev_input = input;
+
+        if self.active.is_none() {
+            if !self.wait(&mut events) {
+                // DAS keeps charging while waiting for the next piece
+                self.auto_shift(input, pressed);
+                return events;
+            }
+            if self.over {
+                return events;
+            }
+            self.spawn_next();
+        }
+        if pressed.hold {
+            self.swap_hold();
+        }
+        if self.over {
+            events.push(GameEvent::GameOver);
+            return events;
+        }
+
+        let hor = self.auto_shift(input, pressed);
+        let Some(piece) = self.active.as_mut() else {
+            return events;
+        };
+
+        let mut moved = false;
+        for _ in 0..hor.abs() {
+            let step = IVec2::new(hor.signum(), 0);
+            if !self
+                .board
+                .can_move(piece.pos, grid_points(&piece.points), step)
+            {
+                break;
+            }
+            piece.pos.x += step.x;
+            moved = true;
+            self.last_rotation = None;
+        }
+
+        let rot = pressed.rotate_cw as i32 - pressed.rotate_ccw as i32;
+        let rot = match rot {
+            _ if pressed.rotate_180 => Some(Rotation::Half),
+            1 => Some(Rotation::Clockwise),
+            -1 => Some(Rotation::Counterclockwise),
+            _ => None,
+        };
+        if let Some(rot) = rot
+            && let Some(kick) = try_rotate(&self.board, piece, rot)
+        {
+            moved = true;
+            // Only the final SRS kick of a quarter turn promotes a T-spin
+            self.last_rotation = match rot {
+                Rotation::Half => Some(0),
+                _ => Some(kick),
+            };
+        }
+
+        let gravity = self.gravity.gravity(self.scoring.level());
+        let drop_type = if input.soft_drop && piece_is_visible(piece) {
+            // Saturating so an "instant" soft drop factor can't overflow
+            let soft = (gravity as f32 * self.handling.soft_drop_factor) as u32;
+            self.fall = self.fall.saturating_add(soft);
+            DropType::Soft
+        } else if pressed.hard_drop {
+            DropType::Hard
+        } else {
+            self.fall = self.fall.saturating_add(gravity);
+            DropType::Normal
+        };
+
+        let rows = match drop_type {
+            DropType::Hard => u32::MAX,
+            // At 20G the piece can't be seen falling at all
+            _ if gravity >= MAX_GRAVITY => {
+                self.fall = 0;
+                u32::MAX
+            }
+            _ => {
+                let rows = self.fall / G;
+                self.fall %= G;
+                rows
+            }
+        };
+        let lines_moved = drop_piece(&self.board, piece, rows) as usize;
+
+        if lines_moved != 0 {
+            self.last_rotation = None;
+            match drop_type {
+                DropType::Normal => (),
+                DropType::Soft => self.scoring.soft_drop(lines_moved),
+                DropType::Hard => self.scoring.hard_drop(lines_moved),
+            };
+        }
+
+        if let DropType::Hard = drop_type {
+            self.place(&mut events);
+            return events;
+        }
+
+        // Reaching a new lowest row gives the piece a fresh set of resets
+        let lock = &mut self.lock;
+        if piece.pos.y < lock.lowest_y {
+            lock.lowest_y = piece.pos.y;
+            lock.resets = 0;
+            lock.timer = 0;
+        }
+
+        if moved && lock.resets < self.handling.lock_move_resets {
+            lock.resets += 1;
+            lock.timer = 0;
+        }
+
+        let grounded = !self
+            .board
+            .can_move(piece.pos, grid_points(&piece.points), IVec2::NEG_Y);
+        if grounded {
+            lock.timer += 1;
+            if lock.timer >= to_ticks(self.handling.lock_delay)
+                || lock.resets >= self.handling.lock_move_resets
+            {
+                self.place(&mut events);
+            }
+        }
+
+        events
+    }
+
+    fn garbage_on_board(&self) -> usize {
+        (0..TOTAL_HEIGHT)
+            .filter(|line| self.board.line_contains(*line, GARBAGE_SQUARE))
+            .count()
+    }
+
+    /// Push new garbage rows up from the bottom until the board holds as many
+    /// as the cheese race allows.
+    ///
+    /// Returns false if the stack was pushed off the top of the board.
+    fn raise_garbage(&mut self) -> bool {
+        let GameMode::Cheese(cheese) = self.mode else {
+            return true;
+        };
+        let mut on_board = self.garbage_on_board();
+        while on_board < cheese.height {
+            let Some(row) = self.garbage.as_mut().and_then(Garbage::next_row) else {
+                break;
+            };
+            if !self.board.push_row(row) {
+                return false;
+            }
+            on_board += 1;
+        }
+        true
+    }
+
+    /// Count down the line clear and entry delays after a piece locks,
+    /// removing the filled lines once the line clear delay is up.
+    ///
+    /// Returns true once the next piece can spawn.
+    fn wait(&mut self, events: &mut Vec<GameEvent>) -> bool {
+        match &mut self.phase {
+            Phase::Falling => true,
+            Phase::LineClear { ticks, .. } | Phase::Entry { ticks } if *ticks > 0 => {
+                *ticks -= 1;
+                false
+            }
+            Phase::LineClear { lines, .. } => {
+                let lines = std::mem::take(lines);
+                self.collapse(&lines, events);
+                self.phase = Phase::Entry {
+                    ticks: to_ticks(self.handling.entry_delay),
+                };
+                self.wait(events)
+            }
+            Phase::Entry { .. } => {
+                self.phase = Phase::Falling;
+                true
+            }
+        }
+    }
+
+    /// Remove filled lines from the board, then bring up more garbage in a
+    /// cheese race.
+    fn collapse(&mut self, lines: &[usize], events: &mut Vec<GameEvent>) {
+        // Lines must be cleared in reverse order
+        for line in lines.iter().rev() {
+            self.board.clear_line(*line);
+        }
+
+        if self.garbage.is_some() {
+            if !self.raise_garbage() {
+                self.over = true;
+                events.push(GameEvent::GameOver);
+            } else if self.garbage_left() == 0 {
+                self.over = true;
+                events.push(GameEvent::Finished);
+            }
+        }
+    }
+
+    fn spawn_next(&mut self) {
+        let piece = self.randomizer.next_piece();
+        self.spawn(piece);
+    }
+
+    /// Put a piece in play, ending the game if it overlaps the stack.
+    fn spawn(&mut self, mut piece: Piece) {
+        piece.pos = SPAWN_POS;
+        let points = grid_points(&piece.points);
+        if !self.board.can_move(piece.pos, points, IVec2::ZERO) {
+            self.over = true;
+        }
+        self.active = Some(piece);
+        self.fall = 0;
+        self.lock = LockDelay::default();
+        self.last_rotation = None;
+    }
+
+    /// Swap the active piece with the held piece, or store it and pull the next
+    /// piece if nothing is held yet.
+    fn swap_hold(&mut self) {
+        if self.hold_used {
+            return;
+        }
+        let Some(piece) = self.active.take() else {
+            return;
+        };
+
+        // Held pieces go back to their spawn orientation
+        match self.hold.replace(PIECES[piece.piece_id].clone()) {
+            Some(held) => self.spawn(held),
+            None => self.spawn_next(),
+        }
+        self.hold_used = true;
+    }
+
+    /// Update delayed auto shift from the held movement buttons and return how
+    /// many cells the active piece should try to move this update, negative
+    /// being left.
+    fn auto_shift(&mut self, input: Input, pressed: Input) -> i32 {
+        let held = |dir: i32| match dir {
+            1 => input.right,
+            -1 => input.left,
+            _ => false,
+        };
+        let shift = &mut self.shift;
+
+        // The most recently pressed direction wins if both are held
+        let mut dir = shift.direction;
+        if pressed.right {
+            dir = 1;
+        } else if pressed.left {
+            dir = -1;
+        } else if !held(dir) {
+            dir = if held(-dir) { -dir } else { 0 };
+        }
+
+        if dir != shift.direction {
+            *shift = AutoShift {
+                direction: dir,
+                held: 0,
+                moved: 0,
+            };
+            return dir;
+        }
+        if dir == 0 {
+            return 0;
+        }
+
+        shift.held += 1;
+        let das = to_ticks(self.handling.das);
+        if shift.held < das {
+            return 0;
+        }
+        let arr = to_ticks(self.handling.arr);
+        if arr == 0 {
+            return dir * BOARD_WIDTH as i32;
+        }
+        let total = ((shift.held - das) / arr) as i32 + 1;
+        let moves = total - shift.moved;
+        shift.moved = total;
+        dir * moves
+    }
+
+    /// Lock the active piece into the board and score the result. Filled lines
+    /// are removed after the line clear delay.
+    fn place(&mut self, events: &mut Vec<GameEvent>) {
+        let Some(piece) = self.active.take() else {
+            return;
+        };
+        let spin = t_spin(&self.board, &piece, self.last_rotation);
+
+        let above = piece
+            .grid_points()
+            .filter(|p| p.y >= BOARD_HEIGHT as i32)
+            .count();
+        let lock_out = above == piece.points.len()
+            || (above > 0 && self.handling.partial_lock_out)
+            || piece.grid_points().any(|p| p.y >= TOTAL_HEIGHT as i32);
+        if lock_out {
+            self.over = true;
+            events.push(GameEvent::GameOver);
+            return;
+        }
+
+        let mut lines_to_clear = BTreeSet::new();
+        for p in piece.grid_points() {
+            lines_to_clear.insert(p.y as usize);
+            self.board.set_tile(p, piece.piece_id);
+        }
+        self.hold_used = false;
+
+        let filled: Vec<_> = lines_to_clear
+            .into_iter()
+            .filter(|line| self.board.is_line_filled(*line))
+            .collect();
+        let lines = filled.len();
+
+        // Nothing will be left once the filled lines are gone
+        let blocks = self.board.state.iter().filter(|t| **t != EMPTY_SQUARE);
+        let perfect_clear = lines != 0 && blocks.count() == lines * BOARD_WIDTH;
+        self.scoring.line_clears(lines, spin);
+        if perfect_clear {
+            self.scoring.perfect_clear(lines);
+        }
+
+        events.push(GameEvent::Locked(Clear {
+            lines,
+            spin,
+            perfect_clear,
+        }));
+
+        if let Some(goal) = self.mode.line_goal() {
+            let lines = self.scoring.lines().min(goal);
+            while self.splits.len() < lines / SPLIT_LINES {
+                self.splits.push(self.elapsed());
+            }
+            if lines == goal {
+                self.over = true;
+                events.push(GameEvent::Finished);
+            }
+        }
+
+        self.phase = if lines == 0 {
+            Phase::Entry {
+                ticks: to_ticks(self.handling.entry_delay),
+            }
+        } else {
+            Phase::LineClear {
+                lines: filled,
+                ticks: to_ticks(self.handling.line_clear_delay),
+            }
+        };
+    }
+}
+
+fn piece_is_visible(piece: &Piece) -> bool {
+    piece
+        .points
+        .iter()
+        .any(|p| in_stage(piece.pos + p.as_ivec2()))
+}
+
+/// The nearest whole number of updates in a number of seconds.
+fn to_ticks(secs: f32) -> u32 {
+    (secs * TICK_RATE as f32).round() as u32
+}
+
+/// Move a piece down by up to `rows`, stopping on anything below it.
+///
+/// Returns the number of rows moved.
+fn drop_piece(board: &Board, piece: &mut Piece, rows: u32) -> u32 {
+    let mut moved = 0;
+    while moved < rows && board.can_move(piece.pos, grid_points(&piece.points), IVec2::NEG_Y) {
+        piece.pos.y -= 1;
+        moved += 1;
+    }
+    moved
+}
+
+/// Rotate a piece using the Super Rotation System, testing each wall kick against
+/// the board in turn.
+///
+/// Returns the index of the kick that was used, or `None` if every kick failed and
+/// the rotation was reverted.
+fn try_rotate(board: &Board, piece: &mut Piece, rot: Rotation) -> Option<usize> {
+    let kicks = piece.kicks(rot);
+    piece.rotate(rot);
+    for (i, &(x, y)) in kicks.iter().enumerate() {
+        if board.can_move(piece.pos, grid_points(&piece.points), IVec2::new(x, y)) {
+            piece.pos += IVec2::new(x, y);
+            return Some(i);
+        }
+    }
+    piece.rotate(rot.opposite());
+    None
+}
+
+/// Check a locking piece for a T-spin using the 3-corner rule. The last action
+/// must have been a rotation, and the final SRS kick always counts as a full
+/// T-spin.
+fn t_spin(board: &Board, piece: &Piece, kick: Option<usize>) -> Spin {
+    let Some(kick) = kick else {
+        return Spin::None;
+    };
+    if piece.piece_id != T.piece_id {
+        return Spin::None;
+    }
+
+    let center = piece.pos;
+    let filled = |[x, y]: [i32; 2]| {
+        let p = center + IVec2::new(x, y);
+        board
+            .get_tile(p)
+            .map_or(!in_stage(p), |tile| tile != EMPTY_SQUARE)
+    };
+    // The two corners on the side the T is pointing, then the two behind it
+    let corners = match piece.rotation {
+        RotationState::Spawn => [[-1, 1], [1, 1], [-1, -1], [1, -1]],
+        RotationState::Right => [[1, 1], [1, -1], [-1, 1], [-1, -1]],
+        RotationState::Two => [[1, -1], [-1, -1], [1, 1], [-1, 1]],
+        RotationState::Left => [[-1, -1], [-1, 1], [1, -1], [1, 1]],
+    };
+    let front = corners[..2].iter().filter(|c| filled(**c)).count();
+    let back = corners[2..].iter().filter(|c| filled(**c)).count();
+
+    if front + back < 3 {
+        Spin::None
+    } else if front == 2 || kick == 4 {
+        Spin::Full
+    } else {
+        Spin::Mini
+    }
+}
+
+fn grid_points(points: &[Vec2]) -> impl Iterator<Item = IVec2> + '_ {
+    points.iter().map(|p| p.floor().as_ivec2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::{Cheese, SPRINT_LINES};
+    use crate::piece::{I, O};
+
+    fn piece_at(piece: Piece, x: i32, y: i32) -> Piece {
+        Piece {
+            pos: IVec2::new(x, y),
+            ..piece
+        }
+    }
+
+    /// Deals the given pieces in order, over and over.
+    struct Cycle {
+        pieces: Vec<Piece>,
+        dealt: usize,
+    }
+
+    impl Randomizer for Cycle {
+        fn next_piece(&mut self) -> Piece {
+            self.dealt += 1;
+            self.pieces[(self.dealt - 1) % self.pieces.len()].clone()
+        }
+
+        fn peek_n(&mut self, n: usize) -> Vec<Piece> {
+            (self.dealt..self.dealt + n)
+                .map(|i| self.pieces[i % self.pieces.len()].clone())
+                .collect()
+        }
+
+        fn seed(&self) -> u64 {
+            0
+        }
+    }
+
+    fn new_game(mode: GameMode, start_level: usize, handling: Handling, pieces: &[Piece]) -> Game {
+        let randomizer = Cycle {
+            pieces: pieces.to_vec(),
+            dealt: 0,
+        };
+        Game::new(
+            mode,
+            GravityCurve::Guideline,
+            start_level,
+            handling,
+            Box::new(randomizer),
+        )
+    }
+
+    /// Update `ticks` times with the same buttons held, returning every event.
+    fn run(game: &mut Game, input: Input, ticks: u32) -> Vec<GameEvent> {
+        (0..ticks).flat_map(|_| game.update(input)).collect()
+    }
+
+    fn locked(events: &[GameEvent]) -> bool {
+        events.iter().any(|e| matches!(e, GameEvent::Locked(_)))
+    }
+
+    /// Update with nothing held until the next piece spawns.
+    ///
+    /// Returns the number of updates it took.
+    fn wait_for_spawn(game: &mut Game) -> u32 {
+        let mut ticks = 0;
+        while game.active().is_none() {
+            game.update(Input::default());
+            ticks += 1;
+            assert!(ticks < 1000, "no piece spawned");
+        }
+        ticks
+    }
+
+    fn active_id(game: &Game) -> Option<usize> {
+        game.active().map(|p| p.piece_id)
+    }
+
+    fn held_id(game: &Game) -> Option<usize> {
+        game.hold().map(|p| p.piece_id)
+    }
+
+    const NOTHING: Input = Input {
+        left: false,
+        right: false,
+        soft_drop: false,
+        hard_drop: false,
+        rotate_cw: false,
+        rotate_ccw: false,
+        rotate_180: false,
+        hold: false,
+    };
+    const HOLD: Input = Input {
+        hold: true,
+        ..NOTHING
+    };
+    const LEFT: Input = Input {
+        left: true,
+        ..NOTHING
+    };
+    const RIGHT: Input = Input {
+        right: true,
+        ..NOTHING
+    };
+    const HARD_DROP: Input = Input {
+        hard_drop: true,
+        ..NOTHING
+    };
+
+    #[test]
+    fn t_wall_kick() {
+        let board = Board::default();
+        let mut piece = piece_at(T, 0, 5);
+        piece.rotate(Rotation::Clockwise);
+
+        // Flat against the left wall there's no room to point up without
+        // moving right
+        let kick = try_rotate(&board, &mut piece, Rotation::Counterclockwise);
+        assert_eq!(kick, Some(1));
+        assert_eq!(piece.pos, IVec2::new(1, 5));
+        assert_eq!(piece.rotation, RotationState::Spawn);
+    }
+
+    #[test]
+    fn t_floor_kick() {
+        let board = Board::default();
+        let mut piece = piece_at(T, 4, 0);
+
+        let kick = try_rotate(&board, &mut piece, Rotation::Clockwise);
+        assert_eq!(kick, Some(2));
+        assert_eq!(piece.pos, IVec2::new(3, 1));
+        assert!(piece.grid_points().all(|p| p.y >= 0));
+    }
+
+    #[test]
+    fn i_wall_kick() {
+        let board = Board::default();
+        let mut piece = piece_at(I, 9, 5);
+        piece.rotate(Rotation::Clockwise);
+        assert!(piece.grid_points().all(|p| p.x == 9));
+
+        let kick = try_rotate(&board, &mut piece, Rotation::Clockwise);
+        assert_eq!(kick, Some(1));
+        assert_eq!(piece.pos, IVec2::new(8, 5));
+        assert!(piece.grid_points().all(|p| (6..=9).contains(&p.x)));
+    }
+
+    #[test]
+    fn i_floor_kick() {
+        let board = Board::default();
+        let mut piece = piece_at(I, 4, 0);
+
+        let kick = try_rotate(&board, &mut piece, Rotation::Clockwise);
+        assert_eq!(kick, Some(4));
+        assert_eq!(piece.pos, IVec2::new(5, 2));
+        assert!(piece.grid_points().all(|p| p.y >= 0));
+    }
+
+    #[test]
+    fn rotation_fails_when_every_kick_is_blocked() {
+        let mut board = Board::default();
+        for x in 0..BOARD_WIDTH as i32 {
+            for y in 0..4 {
+                if x != 4 {
+                    board.set_tile(IVec2::new(x, y), GARBAGE_SQUARE);
+                }
+            }
+        }
+        let mut piece = piece_at(I, 4, 2);
+        piece.rotate(Rotation::Clockwise);
+        let before = piece.clone();
+
+        assert_eq!(try_rotate(&board, &mut piece, Rotation::Clockwise), None);
+        assert_eq!(piece.pos, before.pos);
+        assert_eq!(piece.rotation, before.rotation);
+    }
+
+    /// A T pointing down into a slot in the bottom row, with a block over one
+    /// of its back corners.
+    fn t_slot() -> (Board, Piece) {
+        let mut board = Board::default();
+        for x in (0..BOARD_WIDTH as i32).filter(|x| *x != 4) {
+            board.set_tile(IVec2::new(x, 0), GARBAGE_SQUARE);
+        }
+        board.set_tile(IVec2::new(3, 2), GARBAGE_SQUARE);
+        let mut piece = piece_at(T, 4, 1);
+        piece.rotate(Rotation::Half);
+        (board, piece)
+    }
+
+    #[test]
+    fn t_spin_with_both_front_corners() {
+        let (board, piece) = t_slot();
+        assert_eq!(t_spin(&board, &piece, Some(0)), Spin::Full);
+    }
+
+    #[test]
+    fn t_spin_needs_a_rotation() {
+        let (board, piece) = t_slot();
+        assert_eq!(t_spin(&board, &piece, None), Spin::None);
+    }
+
+    #[test]
+    fn t_spin_needs_three_corners() {
+        let (mut board, piece) = t_slot();
+        board.set_tile(IVec2::new(3, 2), EMPTY_SQUARE);
+        assert_eq!(t_spin(&board, &piece, Some(0)), Spin::None);
+    }
+
+    #[test]
+    fn t_spin_mini_with_one_front_corner() {
+        // Pointing up on the floor, so both back corners are below the board
+        let mut board = Board::default();
+        board.set_tile(IVec2::new(3, 1), GARBAGE_SQUARE);
+        let piece = piece_at(T, 4, 0);
+
+        assert_eq!(t_spin(&board, &piece, Some(1)), Spin::Mini);
+        // The last kick is always a full T-spin
+        assert_eq!(t_spin(&board, &piece, Some(4)), Spin::Full);
+    }
+
+    #[test]
+    fn hold_once_per_drop() {
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[T, I, O]);
+        game.update(NOTHING);
+        assert_eq!(active_id(&game), Some(T.piece_id));
+
+        // Nothing held yet, so the next piece comes out
+        game.update(HOLD);
+        assert_eq!(held_id(&game), Some(T.piece_id));
+        assert_eq!(active_id(&game), Some(I.piece_id));
+        assert!(game.hold_used());
+
+        game.update(NOTHING);
+        game.update(HOLD);
+        assert_eq!(held_id(&game), Some(T.piece_id));
+        assert_eq!(active_id(&game), Some(I.piece_id));
+
+        // Locking a piece frees the hold slot up again
+        game.update(HARD_DROP);
+        assert!(!game.hold_used());
+        wait_for_spawn(&mut game);
+        assert_eq!(active_id(&game), Some(O.piece_id));
+
+        game.update(HOLD);
+        assert_eq!(held_id(&game), Some(O.piece_id));
+        assert_eq!(active_id(&game), Some(T.piece_id));
+        assert!(game.hold_used());
+    }
+
+    #[test]
+    fn held_piece_returns_to_spawn_orientation() {
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[T, I]);
+        game.update(Input {
+            rotate_cw: true,
+            ..NOTHING
+        });
+        assert_eq!(game.active().unwrap().rotation, RotationState::Right);
+
+        game.update(HOLD);
+        let held = game.hold().unwrap();
+        assert_eq!(held.rotation, RotationState::Spawn);
+        assert_eq!(held.points, T.points);
+    }
+
+    /// The level where pieces land as soon as they spawn, so they start
+    /// counting down their lock delay right away.
+    const LEVEL_20G: usize = 19;
+
+    #[test]
+    fn lock_delay() {
+        let mut game = new_game(GameMode::Marathon, LEVEL_20G, Handling::default(), &[O]);
+        let events = run(&mut game, NOTHING, 29);
+        assert!(!locked(&events));
+        assert!(game.active().is_some());
+
+        assert!(locked(&game.update(NOTHING)));
+        assert!(game.active().is_none());
+    }
+
+    #[test]
+    fn moving_resets_lock_delay() {
+        let mut game = new_game(GameMode::Marathon, LEVEL_20G, Handling::default(), &[O]);
+        for _ in 0..3 {
+            assert!(!locked(&run(&mut game, NOTHING, 20)));
+            assert!(!locked(&game.update(LEFT)));
+        }
+
+        // A full lock delay after the last move
+        assert!(!locked(&run(&mut game, NOTHING, 28)));
+        assert!(locked(&game.update(NOTHING)));
+    }
+
+    #[test]
+    fn lock_delay_resets_are_capped() {
+        let mut game = new_game(GameMode::Marathon, LEVEL_20G, Handling::default(), &[O]);
+        game.update(NOTHING);
+
+        // Shuffling back and forth locks the piece on the last reset, well
+        // before the lock delay is up
+        for i in 1..=LOCK_MOVE_RESETS {
+            let input = if i % 2 == 0 { RIGHT } else { LEFT };
+            let events = game.update(input);
+            assert_eq!(locked(&events), i == LOCK_MOVE_RESETS, "move {i}");
+        }
+    }
+
+    fn active_x(game: &Game) -> i32 {
+        game.active().unwrap().pos.x
+    }
+
+    #[test]
+    fn auto_shift() {
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[O]);
+        game.update(RIGHT);
+        assert_eq!(active_x(&game), SPAWN_POS.x + 1);
+
+        // Nothing more until DAS is charged, 10 updates
+        run(&mut game, RIGHT, 9);
+        assert_eq!(active_x(&game), SPAWN_POS.x + 1);
+
+        // Then a move every 2 updates
+        game.update(RIGHT);
+        assert_eq!(active_x(&game), SPAWN_POS.x + 2);
+        game.update(RIGHT);
+        assert_eq!(active_x(&game), SPAWN_POS.x + 2);
+        game.update(RIGHT);
+        assert_eq!(active_x(&game), SPAWN_POS.x + 3);
+
+        // Letting go starts over
+        game.update(NOTHING);
+        run(&mut game, LEFT, 10);
+        assert_eq!(active_x(&game), SPAWN_POS.x + 2);
+        game.update(LEFT);
+        assert_eq!(active_x(&game), SPAWN_POS.x + 1);
+    }
+
+    #[test]
+    fn zero_arr_shifts_to_the_wall() {
+        let handling = Handling {
+            arr: 0.0,
+            ..Default::default()
+        };
+        let mut game = new_game(GameMode::Marathon, 0, handling, &[O]);
+        run(&mut game, LEFT, 10);
+        assert_eq!(active_x(&game), SPAWN_POS.x - 1);
+
+        game.update(LEFT);
+        // The O's left column is one left of its position
+        assert_eq!(active_x(&game), 1);
+    }
+
+    #[test]
+    fn instant_soft_drop() {
+        let handling = Handling {
+            soft_drop_factor: f32::INFINITY,
+            ..Default::default()
+        };
+        let mut game = new_game(GameMode::Marathon, 0, handling, &[O]);
+        // Leaves part of a row of gravity behind
+        game.update(NOTHING);
+        game.update(Input {
+            soft_drop: true,
+            ..NOTHING
+        });
+        assert_eq!(game.active().unwrap().pos.y, 1);
+    }
+
+    #[test]
+    fn das_charges_during_entry_delay() {
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[O]);
+        game.update(HARD_DROP);
+
+        // Held through the 6 update entry delay, then 4 more to charge
+        run(&mut game, RIGHT, 6);
+        assert!(game.active().is_none());
+        run(&mut game, RIGHT, 4);
+        assert_eq!(active_x(&game), SPAWN_POS.x);
+        game.update(RIGHT);
+        assert_eq!(active_x(&game), SPAWN_POS.x + 1);
+    }
+
+    /// Drop a vertical I into a well at the right wall of four nearly full rows.
+    ///
+    /// Returns the events from the update the I locked in.
+    fn tetris(game: &mut Game) -> Vec<GameEvent> {
+        wait_for_spawn(game);
+        for y in 0..4 {
+            for x in 0..BOARD_WIDTH as i32 - 1 {
+                game.board.set_tile(IVec2::new(x, y), GARBAGE_SQUARE);
+            }
+        }
+        game.update(Input {
+            rotate_cw: true,
+            ..NOTHING
+        });
+        run(game, RIGHT, 2);
+        game.update(Input {
+            hard_drop: true,
+            ..RIGHT
+        })
+    }
+
+    #[test]
+    fn sprint_splits_and_finish() {
+        let handling = Handling {
+            das: 0.0,
+            arr: 0.0,
+            ..Default::default()
+        };
+        let mut game = new_game(GameMode::Sprint, 0, handling, &[I]);
+        for i in 1..=SPRINT_LINES / 4 {
+            let events = tetris(&mut game);
+            let lines = i * 4;
+            assert_eq!(game.scoring().lines(), lines);
+            assert_eq!(game.splits().len(), lines / SPLIT_LINES);
+            assert_eq!(events.contains(&GameEvent::Finished), lines == SPRINT_LINES);
+        }
+
+        assert!(game.is_over());
+        assert!(game.splits().is_sorted());
+        assert_eq!(game.splits().last(), Some(&game.elapsed()));
+
+        // The clock stops at the finish
+        let elapsed = game.elapsed();
+        assert!(game.update(NOTHING).is_empty());
+        assert_eq!(game.elapsed(), elapsed);
+    }
+
+    /// Tap left or right until the active piece is at column `x`.
+    fn shift_to(game: &mut Game, x: i32) {
+        while active_x(game) != x {
+            game.update(if active_x(game) < x { RIGHT } else { LEFT });
+            game.update(NOTHING);
+        }
+    }
+
+    /// The column of the hole in a row of garbage.
+    fn hole(game: &Game, line: usize) -> i32 {
+        (0..BOARD_WIDTH as i32)
+            .find(|x| game.board.get_tile(IVec2::new(*x, line as i32)) == Some(EMPTY_SQUARE))
+            .unwrap()
+    }
+
+    /// Drop a vertical I into the hole in the bottom row of garbage.
+    fn fill_hole(game: &mut Game) -> Vec<GameEvent> {
+        wait_for_spawn(game);
+        game.update(Input {
+            rotate_cw: true,
+            ..NOTHING
+        });
+        shift_to(game, hole(game, 0));
+        game.update(HARD_DROP)
+    }
+
+    #[test]
+    fn garbage_rises_as_rows_are_cleared() {
+        let cheese = Cheese {
+            rows: 3,
+            height: 2,
+            messiness: 0,
+        };
+        let mut game = new_game(GameMode::Cheese(cheese), 0, Handling::default(), &[I]);
+        assert_eq!(game.garbage_on_board(), 2);
+        assert_eq!(game.garbage_left(), 3);
+        let x = hole(&game, 0);
+        assert_eq!(hole(&game, 1), x);
+
+        let events = fill_hole(&mut game);
+        assert_eq!(
+            events,
+            [GameEvent::Locked(Clear {
+                lines: 2,
+                ..Default::default()
+            })]
+        );
+        wait_for_spawn(&mut game);
+
+        // The last row pushed what was left of the I back up
+        assert_eq!(game.garbage_left(), 1);
+        assert_eq!(hole(&game, 0), x);
+        assert!(game.board.line_contains(0, GARBAGE_SQUARE));
+        for y in 1..3 {
+            assert_eq!(game.board.get_tile(IVec2::new(x, y)), Some(I.piece_id));
+        }
+        assert!(!game.board.line_contains(1, GARBAGE_SQUARE));
+    }
+
+    #[test]
+    fn clearing_every_garbage_row_finishes_a_cheese_race() {
+        let cheese = Cheese {
+            rows: 2,
+            height: 2,
+            messiness: 0,
+        };
+        let mut game = new_game(GameMode::Cheese(cheese), 0, Handling::default(), &[I]);
+        fill_hole(&mut game);
+        assert!(!game.is_over());
+
+        // Finished once the lines are removed
+        let ticks = to_ticks(LINE_CLEAR_DELAY) + 1;
+        let events = run(&mut game, NOTHING, ticks);
+        assert_eq!(events, [GameEvent::Finished]);
+        assert!(game.is_over());
+        assert_eq!(game.garbage_left(), 0);
+    }
+
+    #[test]
+    fn gravity() {
+        // A row a second, with a tick more from rounding gravity down
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[O]);
+        run(&mut game, NOTHING, TICK_RATE);
+        assert_eq!(game.active().unwrap().pos.y, SPAWN_POS.y);
+        game.update(NOTHING);
+        assert_eq!(game.active().unwrap().pos.y, SPAWN_POS.y - 1);
+    }
+
+    #[test]
+    fn pieces_land_as_they_spawn_at_20g() {
+        let mut game = new_game(GameMode::Marathon, LEVEL_20G, Handling::default(), &[O]);
+        game.update(NOTHING);
+        // The O's bottom row is one below its position
+        assert_eq!(game.active().unwrap().pos.y, 1);
+    }
+
+    #[test]
+    fn entry_delay() {
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[O]);
+        game.update(HARD_DROP);
+        assert!(game.active().is_none());
+        assert!(game.clearing().is_none());
+
+        // 6 updates of waiting, then the next piece appears
+        assert_eq!(wait_for_spawn(&mut game), to_ticks(ENTRY_DELAY) + 1);
+    }
+
+    #[test]
+    fn line_clear_delay() {
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[O]);
+        // Two rows waiting on an O in the middle
+        for x in (0..BOARD_WIDTH as i32).filter(|x| !(4..6).contains(x)) {
+            for y in 0..2 {
+                game.board.set_tile(IVec2::new(x, y), GARBAGE_SQUARE);
+            }
+        }
+        game.update(HARD_DROP);
+        assert_eq!(game.clearing(), Some(([0, 1].as_slice(), 0.0)));
+
+        // The filled lines stay on the board until the delay is up
+        let delay = to_ticks(LINE_CLEAR_DELAY);
+        run(&mut game, NOTHING, delay);
+        assert_eq!(game.clearing(), Some(([0, 1].as_slice(), 1.0)));
+        assert!(game.board.is_line_filled(0));
+
+        game.update(NOTHING);
+        assert!(game.clearing().is_none());
+        assert!(game.board.state.iter().all(|t| *t == EMPTY_SQUARE));
+
+        // Followed by the entry delay
+        assert_eq!(wait_for_spawn(&mut game), to_ticks(ENTRY_DELAY));
+    }
+
+    #[test]
+    fn no_delays() {
+        let handling = Handling {
+            entry_delay: 0.0,
+            line_clear_delay: 0.0,
+            ..Default::default()
+        };
+        let mut game = new_game(GameMode::Marathon, 0, handling, &[O]);
+        for x in (0..BOARD_WIDTH as i32).filter(|x| !(4..6).contains(x)) {
+            game.board.set_tile(IVec2::new(x, 0), GARBAGE_SQUARE);
+        }
+        game.update(HARD_DROP);
+
+        // Cleared and spawned on the very next update
+        game.update(NOTHING);
+        assert!(game.active().is_some());
+        assert!(!game.board.is_line_filled(0));
+    }
+
+    /// Fill the two middle columns up to `height` rows, leaving every row
+    /// with gaps so nothing is cleared.
+    fn middle_tower(game: &mut Game, height: i32) {
+        for y in 0..height {
+            for x in 4..6 {
+                game.board.set_tile(IVec2::new(x, y), GARBAGE_SQUARE);
+            }
+        }
+    }
+
+    #[test]
+    fn block_out() {
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[O]);
+        game.board.set_tile(SPAWN_POS, GARBAGE_SQUARE);
+        assert_eq!(game.update(NOTHING), [GameEvent::GameOver]);
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn lock_out() {
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[O]);
+        middle_tower(&mut game, BOARD_HEIGHT as i32);
+        assert_eq!(game.update(HARD_DROP), [GameEvent::GameOver]);
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn partial_lock_out() {
+        let mut game = new_game(GameMode::Marathon, 0, Handling::default(), &[O]);
+        middle_tower(&mut game, BOARD_HEIGHT as i32 - 1);
+        // Half the O is still on the visible board
+        assert!(locked(&game.update(HARD_DROP)));
+        assert!(!game.is_over());
+
+        let handling = Handling {
+            partial_lock_out: true,
+            ..Default::default()
+        };
+        let mut game = new_game(GameMode::Marathon, 0, handling, &[O]);
+        middle_tower(&mut game, BOARD_HEIGHT as i32 - 1);
+        assert_eq!(game.update(HARD_DROP), [GameEvent::GameOver]);
+        assert!(game.is_over());
+    }
+}
@@ -0,0 +1,54 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::BOARD_WIDTH;
+use crate::board::{EMPTY_SQUARE, GARBAGE_SQUARE};
+use crate::mode::Cheese;
+
+/// Mixed into the game's seed so the holes don't come from the same random
+/// sequence the pieces are dealt from
+const SEED_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Generates rows of garbage with a single hole for a cheese race.
+pub struct Garbage {
+    /// Rows that haven't risen onto the board yet
+    remaining: usize,
+    messiness: u32,
+    hole: usize,
+    rng: ChaCha8Rng,
+}
+
+impl Garbage {
+    /// Garbage for a game started from the given seed.
+    pub fn new(cheese: Cheese, seed: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed ^ SEED_SALT);
+        Self {
+            remaining: cheese.rows,
+            messiness: cheese.messiness,
+            hole: rng.gen_range(0..BOARD_WIDTH),
+            rng,
+        }
+    }
+
+    /// Rows that haven't risen onto the board yet.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// The next row of garbage, or `None` once every row has been generated.
+    pub fn next_row(&mut self) -> Option<[usize; BOARD_WIDTH]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        if self.rng.gen_ratio(self.messiness.min(100), 100) {
+            // Always move somewhere else
+            let offset = self.rng.gen_range(1..BOARD_WIDTH);
+            self.hole = (self.hole + offset) % BOARD_WIDTH;
+        }
+        let mut row = [GARBAGE_SQUARE; BOARD_WIDTH];
+        row[self.hole] = EMPTY_SQUARE;
+        Some(row)
+    }
+}
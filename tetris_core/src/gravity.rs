@@ -0,0 +1,125 @@
+use crate::{G, MAX_GRAVITY, TICK_RATE};
+
+/// Ticks for a piece to fall one row on the NES, by level. Every level past the
+/// end of the table is the same as the last.
+const NES_FRAMES_PER_ROW: [u32; 30] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+    1,
+];
+
+/// TGM's internal gravity in 1/256 G, as the level each value starts at and the
+/// value. Gravity drops back down at level 200 before climbing to 20G.
+#[rustfmt::skip]
+const TGM_GRAVITY: [(usize, u32); 30] = [
+    (0, 4), (30, 6), (35, 8), (40, 10), (50, 12), (60, 16), (70, 32), (80, 48),
+    (90, 64), (100, 80), (120, 96), (140, 112), (160, 128), (170, 144), (200, 4),
+    (220, 32), (230, 64), (233, 96), (236, 128), (239, 160), (243, 192), (247, 224),
+    (251, 256), (300, 512), (330, 768), (360, 1024), (400, 1280), (420, 1024),
+    (450, 768), (500, 5120),
+];
+
+/// How fast pieces fall as the level goes up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GravityCurve {
+    /// The Tetris Guideline, `(0.8 - (level - 1) * 0.007)^(level - 1)` seconds
+    /// per row counting levels from 1. Reaches 20G at level 19.
+    #[default]
+    Guideline,
+    /// The NES frames per row table. Never goes faster than one row a tick.
+    Nes,
+    /// TGM's internal gravity table. TGM levels go up with every line, so each
+    /// of our levels counts as 10 of them.
+    Tgm,
+}
+
+impl GravityCurve {
+    pub const ALL: [GravityCurve; 3] = [
+        GravityCurve::Guideline,
+        GravityCurve::Nes,
+        GravityCurve::Tgm,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GravityCurve::Guideline => "Guideline",
+            GravityCurve::Nes => "NES",
+            GravityCurve::Tgm => "TGM",
+        }
+    }
+
+    /// Gravity at a level counting from 0, in fractions of [G] per tick, never
+    /// more than [MAX_GRAVITY].
+    pub fn gravity(&self, level: usize) -> u32 {
+        let gravity = match self {
+            GravityCurve::Guideline => {
+                // Past level 20 the formula stops making sense, but it's well
+                // past 20G by then anyway
+                let n = level.min(20);
+                let base = 0.8 - n as f64 * 0.007;
+                // Multiplied out rather than using powi, which isn't guaranteed
+                // to give the same result on every platform
+                let secs = (0..n).fold(1.0, |secs, _| secs * base);
+                let rows_per_tick = 1.0 / (secs * TICK_RATE as f64);
+                (rows_per_tick * G as f64).min(MAX_GRAVITY as f64) as u32
+            }
+            GravityCurve::Nes => {
+                let i = level.min(NES_FRAMES_PER_ROW.len() - 1);
+                G / NES_FRAMES_PER_ROW[i]
+            }
+            GravityCurve::Tgm => {
+                let i = TGM_GRAVITY.partition_point(|(start, _)| *start <= level * 10);
+                TGM_GRAVITY[i - 1].1 * G / 256
+            }
+        };
+        gravity.min(MAX_GRAVITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guideline() {
+        // A row a second on the first level
+        assert_eq!(GravityCurve::Guideline.gravity(0), G / TICK_RATE);
+        assert!(GravityCurve::Guideline.gravity(17) < MAX_GRAVITY);
+        assert_eq!(GravityCurve::Guideline.gravity(18), MAX_GRAVITY);
+    }
+
+    #[test]
+    fn nes() {
+        assert_eq!(GravityCurve::Nes.gravity(0), G / 48);
+        assert_eq!(GravityCurve::Nes.gravity(28), G / 2);
+        assert_eq!(GravityCurve::Nes.gravity(29), G);
+        assert_eq!(GravityCurve::Nes.gravity(100), G);
+    }
+
+    #[test]
+    fn tgm() {
+        assert_eq!(GravityCurve::Tgm.gravity(0), 4 * G / 256);
+        assert_eq!(GravityCurve::Tgm.gravity(19), 144 * G / 256);
+        // Slows back down at TGM level 200
+        assert_eq!(GravityCurve::Tgm.gravity(20), 4 * G / 256);
+        assert_eq!(GravityCurve::Tgm.gravity(50), MAX_GRAVITY);
+    }
+
+    #[test]
+    fn capped_at_20g() {
+        for curve in GravityCurve::ALL {
+            for level in 0..1000 {
+                assert!(curve.gravity(level) <= MAX_GRAVITY, "{}", curve.name());
+            }
+        }
+    }
+
+    #[test]
+    fn never_slows_down() {
+        for curve in [GravityCurve::Guideline, GravityCurve::Nes] {
+            for level in 1..100 {
+                assert!(curve.gravity(level) >= curve.gravity(level - 1));
+            }
+        }
+    }
+}
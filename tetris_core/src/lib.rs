@@ -0,0 +1,38 @@
+//! The rules of the game, with no dependency on Bevy or any other frontend.
+//!
+//! [Game] owns the board, active piece, hold slot, randomizer and score. A frontend
+//! feeds it [Input] every frame and reacts to the [GameEvent]s it returns.
+
+pub mod board;
+pub mod game;
+pub mod garbage;
+pub mod gravity;
+pub mod mode;
+pub mod piece;
+pub mod randomizer;
+pub mod replay;
+pub mod score;
+pub mod shuffle_bag;
+
+pub use board::{Board, EMPTY_SQUARE, GARBAGE_SQUARE, TOTAL_HEIGHT};
+pub use game::{Clear, Game, GameEvent, Handling, Input};
+pub use gravity::GravityCurve;
+pub use mode::{Cheese, GameMode};
+pub use piece::{PIECES, Piece, Rotation, RotationState};
+pub use randomizer::{Randomizer, RandomizerKind};
+pub use replay::{REPLAY_VERSION, Replay};
+pub use score::{MAX_START_LEVEL, Scoring, Spin};
+pub use shuffle_bag::ShuffleBag;
+
+pub const BOARD_WIDTH: usize = 10;
+pub const BOARD_HEIGHT: usize = 20;
+/// Hidden rows above the visible board. Pieces spawn and can lock in here,
+/// though usually not without ending the game.
+pub const BUFFER_HEIGHT: usize = 20;
+/// Game updates per second
+pub const TICK_RATE: u32 = 60;
+/// Gravity is measured in fractions of a cell per update. `G` of them is one
+/// cell per update.
+pub const G: u32 = 65536;
+/// The fastest gravity, where pieces drop to the bottom as soon as they appear
+pub const MAX_GRAVITY: u32 = 20 * G;
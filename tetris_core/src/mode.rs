@@ -0,0 +1,82 @@
+/// Lines to clear to finish a sprint
+pub const SPRINT_LINES: usize = 40;
+/// A split time is recorded every time this many lines are cleared
+pub const SPLIT_LINES: usize = 10;
+/// Seconds to score as much as possible in ultra
+pub const ULTRA_TIME: f32 = 120.0;
+
+/// Settings for a cheese race.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cheese {
+    /// Garbage rows to clear in total
+    pub rows: usize,
+    /// The most garbage rows on the board at once. More rise from the bottom
+    /// as they're cleared.
+    pub height: usize,
+    /// Percent chance the hole moves between one garbage row and the next
+    pub messiness: u32,
+}
+
+impl Cheese {
+    pub const STANDARD: Cheese = Cheese {
+        rows: 18,
+        height: 10,
+        messiness: 30,
+    };
+}
+
+impl Default for Cheese {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+/// What the player is trying to do, and when the game ends.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameMode {
+    /// Play until topping out while the fall speed keeps increasing.
+    #[default]
+    Marathon,
+    /// Clear [SPRINT_LINES] lines as fast as possible.
+    Sprint,
+    /// Score as much as possible in [ULTRA_TIME] seconds.
+    Ultra,
+    /// Dig through rows of garbage as fast as possible.
+    Cheese(Cheese),
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 4] = [
+        GameMode::Marathon,
+        GameMode::Sprint,
+        GameMode::Ultra,
+        GameMode::Cheese(Cheese::STANDARD),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Sprint => "Sprint",
+            GameMode::Ultra => "Ultra",
+            GameMode::Cheese(_) => "Cheese",
+        }
+    }
+
+    /// Lines that finish the game once cleared, if any.
+    pub fn line_goal(&self) -> Option<usize> {
+        match self {
+            GameMode::Marathon | GameMode::Ultra | GameMode::Cheese(_) => None,
+            GameMode::Sprint => Some(SPRINT_LINES),
+        }
+    }
+
+    /// Seconds until the game ends, if it's timed.
+    pub fn time_limit(&self) -> Option<f32> {
+        match self {
+            GameMode::Marathon | GameMode::Sprint | GameMode::Cheese(_) => None,
+            GameMode::Ultra => Some(ULTRA_TIME),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use glam::{IVec2, Mat2, Vec2};
+
+const ROT_CLOCKWISE: Mat2 = Mat2::from_cols_array(&[0., -1., 1., 0.]);
+
+#[derive(Debug, Clone, Default)]
+pub struct Piece {
+    pub points: [Vec2; 4],
+    pub piece_id: usize,
+    pub pos: IVec2,
+    pub rotation: RotationState,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Rotation {
+    Clockwise,
+    Counterclockwise,
+    /// A 180 degree turn
+    Half,
+}
+
+impl Rotation {
+    pub fn opposite(&self) -> Rotation {
+        match self {
+            Rotation::Clockwise => Rotation::Counterclockwise,
+            Rotation::Counterclockwise => Rotation::Clockwise,
+            Rotation::Half => Rotation::Half,
+        }
+    }
+}
+
+/// Rotation state as used by the Super Rotation System: spawn (0), clockwise (R),
+/// two rotations (2) and counterclockwise (L)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RotationState {
+    #[default]
+    Spawn,
+    Right,
+    Two,
+    Left,
+}
+
+impl RotationState {
+    pub fn rotated(&self, direction: Rotation) -> RotationState {
+        use RotationState::*;
+        match (self, direction) {
+            (Spawn, Rotation::Clockwise) => Right,
+            (Right, Rotation::Clockwise) => Two,
+            (Two, Rotation::Clockwise) => Left,
+            (Left, Rotation::Clockwise) => Spawn,
+            (Spawn, Rotation::Counterclockwise) => Left,
+            (Left, Rotation::Counterclockwise) => Two,
+            (Two, Rotation::Counterclockwise) => Right,
+            (Right, Rotation::Counterclockwise) => Spawn,
+            (_, Rotation::Half) => self
+                .rotated(Rotation::Clockwise)
+                .rotated(Rotation::Clockwise),
+        }
+    }
+
+    /// Index into the kick tables for a rotation from this state in the given direction.
+    fn kick_index(&self, direction: Rotation) -> usize {
+        use RotationState::*;
+        match (self, direction) {
+            (Spawn, Rotation::Clockwise) => 0,
+            (Right, Rotation::Counterclockwise) => 1,
+            (Right, Rotation::Clockwise) => 2,
+            (Two, Rotation::Counterclockwise) => 3,
+            (Two, Rotation::Clockwise) => 4,
+            (Left, Rotation::Counterclockwise) => 5,
+            (Left, Rotation::Clockwise) => 6,
+            (Spawn, Rotation::Counterclockwise) => 7,
+            (_, Rotation::Half) => unreachable!("half turns have their own kick table"),
+        }
+    }
+}
+
+/// SRS wall kick offsets for the J, L, S, T and Z pieces, in the order
+/// 0->R, R->0, R->2, 2->R, 2->L, L->2, L->0, 0->L
+#[rustfmt::skip]
+const JLSTZ_KICKS: [[(i32, i32); 5]; 8] = [
+    [(0, 0), (-1, 0), (-1,  1), (0, -2), (-1, -2)],
+    [(0, 0), ( 1, 0), ( 1, -1), (0,  2), ( 1,  2)],
+    [(0, 0), ( 1, 0), ( 1, -1), (0,  2), ( 1,  2)],
+    [(0, 0), (-1, 0), (-1,  1), (0, -2), (-1, -2)],
+    [(0, 0), ( 1, 0), ( 1,  1), (0, -2), ( 1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0,  2), (-1,  2)],
+    [(0, 0), (-1, 0), (-1, -1), (0,  2), (-1,  2)],
+    [(0, 0), ( 1, 0), ( 1,  1), (0, -2), ( 1, -2)],
+];
+
+/// Wall kick offsets for a half turn of any piece. SRS doesn't define these, so
+/// this is the simple set many modern games use.
+const HALF_TURN_KICKS: [(i32, i32); 6] = [(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)];
+
+/// SRS wall kick offsets for the I piece, same order as [JLSTZ_KICKS]
+#[rustfmt::skip]
+const I_KICKS: [[(i32, i32); 5]; 8] = [
+    [(0, 0), (-2, 0), ( 1, 0), (-2, -1), ( 1,  2)],
+    [(0, 0), ( 2, 0), (-1, 0), ( 2,  1), (-1, -2)],
+    [(0, 0), (-1, 0), ( 2, 0), (-1,  2), ( 2, -1)],
+    [(0, 0), ( 1, 0), (-2, 0), ( 1, -2), (-2,  1)],
+    [(0, 0), ( 2, 0), (-1, 0), ( 2,  1), (-1, -2)],
+    [(0, 0), (-2, 0), ( 1, 0), (-2, -1), ( 1,  2)],
+    [(0, 0), ( 1, 0), (-2, 0), ( 1, -2), (-2,  1)],
+    [(0, 0), (-1, 0), ( 2, 0), (-1,  2), ( 2, -1)],
+];
+
+impl Piece {
+    pub fn grid_points(&self) -> impl Iterator<Item = IVec2> + '_ {
+        let pos = self.pos;
+        self.points.iter().map(move |p| pos + p.floor().as_ivec2())
+    }
+
+    pub fn rotate(&mut self, direction: Rotation) {
+        let rot = match direction {
+            Rotation::Clockwise => ROT_CLOCKWISE,
+            Rotation::Counterclockwise => -ROT_CLOCKWISE,
+            Rotation::Half => -Mat2::IDENTITY,
+        };
+        for p in self.points.iter_mut() {
+            *p = rot.mul_vec2(*p);
+        }
+        self.rotation = self.rotation.rotated(direction);
+    }
+
+    /// The wall kick offsets to try, in order, when rotating from the current
+    /// rotation state in the given direction.
+    pub fn kicks(&self, direction: Rotation) -> &'static [(i32, i32)] {
+        match (self.piece_id, direction) {
+            (3, _) => &[(0, 0)],
+            (_, Rotation::Half) => &HALF_TURN_KICKS,
+            (0, _) => &I_KICKS[self.rotation.kick_index(direction)],
+            _ => &JLSTZ_KICKS[self.rotation.kick_index(direction)],
+        }
+    }
+}
+
+pub const I: Piece = Piece {
+    points: [
+        Vec2::from_array([-1.5, 0.5]),
+        Vec2::from_array([-0.5, 0.5]),
+        Vec2::from_array([0.5, 0.5]),
+        Vec2::from_array([1.5, 0.5]),
+    ],
+    piece_id: 0,
+    pos: IVec2::ZERO,
+    rotation: RotationState::Spawn,
+};
+
+pub const J: Piece = Piece {
+    points: [
+        Vec2::from_array([-1., 1.]),
+        Vec2::from_array([-1., 0.]),
+        Vec2::from_array([0., 0.]),
+        Vec2::from_array([1., 0.]),
+    ],
+    piece_id: 1,
+    pos: IVec2::ZERO,
+    rotation: RotationState::Spawn,
+};
+
+pub const L: Piece = Piece {
+    points: [
+        Vec2::from_array([-1., 0.]),
+        Vec2::from_array([0., 0.]),
+        Vec2::from_array([1., 0.]),
+        Vec2::from_array([1., 1.]),
+    ],
+    piece_id: 2,
+    pos: IVec2::ZERO,
+    rotation: RotationState::Spawn,
+};
+
+pub const O: Piece = Piece {
+    points: [
+        Vec2::from_array([-0.5, 0.5]),
+        Vec2::from_array([-0.5, -0.5]),
+        Vec2::from_array([0.5, -0.5]),
+        Vec2::from_array([0.5, 0.5]),
+    ],
+    piece_id: 3,
+    pos: IVec2::ZERO,
+    rotation: RotationState::Spawn,
+};
+
+pub const S: Piece = Piece {
+    points: [
+        Vec2::from_array([-1., 0.]),
+        Vec2::from_array([0., -0.]),
+        Vec2::from_array([0., 1.]),
+        Vec2::from_array([1., 1.]),
+    ],
+    piece_id: 4,
+    pos: IVec2::ZERO,
+    rotation: RotationState::Spawn,
+};
+
+pub const T: Piece = Piece {
+    points: [
+        Vec2::from_array([-1., 0.]),
+        Vec2::from_array([0., 0.]),
+        Vec2::from_array([0., 1.]),
+        Vec2::from_array([1., 0.]),
+    ],
+    piece_id: 5,
+    pos: IVec2::ZERO,
+    rotation: RotationState::Spawn,
+};
+
+pub const Z: Piece = Piece {
+    points: [
+        Vec2::from_array([-1., 1.]),
+        Vec2::from_array([0., 1.]),
+        Vec2::from_array([0., 0.]),
+        Vec2::from_array([1., 0.]),
+    ],
+    piece_id: 6,
+    pos: IVec2::ZERO,
+    rotation: RotationState::Spawn,
+};
+
+pub const PIECES: [Piece; 7] = [I, J, L, O, S, T, Z];
@@ -0,0 +1,270 @@
+use std::collections::VecDeque;
+
+use rand::{Rng, SeedableRng, seq::SliceRandom};
+use rand_chacha::ChaCha8Rng;
+
+use crate::piece::{I, J, L, PIECES, Piece, S, T, Z};
+use crate::shuffle_bag::ShuffleBag;
+
+/// Decides which pieces are dealt and in what order.
+pub trait Randomizer: Send + Sync {
+    /// Deal the next piece.
+    fn next_piece(&mut self) -> Piece;
+
+    /// The next `n` pieces that will be dealt, in order, without dealing them.
+    fn peek_n(&mut self, n: usize) -> Vec<Piece>;
+
+    /// The seed the randomizer was created from.
+    fn seed(&self) -> u64;
+}
+
+/// The available randomizers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RandomizerKind {
+    /// All seven pieces, shuffled.
+    #[default]
+    SevenBag,
+    /// Two of each piece, shuffled.
+    FourteenBag,
+    /// Every piece is equally likely, every time.
+    Random,
+    /// Rerolls once if the piece is the same as the last one.
+    Nes,
+    /// Rolls up to 4 times before accepting a piece from the last 4 dealt.
+    Tgm,
+    /// Rolls up to 6 times before accepting a piece from the last 4 dealt.
+    Tgm2,
+}
+
+impl RandomizerKind {
+    pub const ALL: [RandomizerKind; 6] = [
+        RandomizerKind::SevenBag,
+        RandomizerKind::FourteenBag,
+        RandomizerKind::Random,
+        RandomizerKind::Nes,
+        RandomizerKind::Tgm,
+        RandomizerKind::Tgm2,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RandomizerKind::SevenBag => "7-Bag",
+            RandomizerKind::FourteenBag => "14-Bag",
+            RandomizerKind::Random => "Random",
+            RandomizerKind::Nes => "NES",
+            RandomizerKind::Tgm => "TGM",
+            RandomizerKind::Tgm2 => "TGM2",
+        }
+    }
+
+    pub fn create(&self, seed: u64) -> Box<dyn Randomizer> {
+        match self {
+            RandomizerKind::SevenBag => Box::new(ShuffleBag::with_seed(seed)),
+            RandomizerKind::FourteenBag => Box::new(ShuffleBag::with_copies(2, seed)),
+            RandomizerKind::Random => Box::new(Generated::new(PureRandom, seed)),
+            RandomizerKind::Nes => Box::new(Generated::new(Nes::default(), seed)),
+            RandomizerKind::Tgm => Box::new(Generated::new(Tgm::new(4, [Z, Z, Z, Z]), seed)),
+            RandomizerKind::Tgm2 => Box::new(Generated::new(Tgm::new(6, [Z, S, S, Z]), seed)),
+        }
+    }
+}
+
+/// Picks one piece id at a time, possibly based on what came before.
+trait Generator: Send + Sync {
+    fn generate(&mut self, rng: &mut ChaCha8Rng) -> usize;
+}
+
+/// A randomizer built from a [Generator]. Pieces are generated ahead of time
+/// when peeked so the dealt sequence is the same whether or not it was previewed.
+struct Generated<G> {
+    generator: G,
+    queue: VecDeque<usize>,
+    seed: u64,
+    /// A named algorithm rather than `StdRng`, which rand is free to change
+    /// between versions, so saved replays keep dealing the same pieces
+    rng: ChaCha8Rng,
+}
+
+impl<G: Generator> Generated<G> {
+    fn new(generator: G, seed: u64) -> Self {
+        Self {
+            generator,
+            queue: VecDeque::new(),
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<G: Generator> Randomizer for Generated<G> {
+    fn next_piece(&mut self) -> Piece {
+        let id = match self.queue.pop_front() {
+            Some(id) => id,
+            None => self.generator.generate(&mut self.rng),
+        };
+        PIECES[id].clone()
+    }
+
+    fn peek_n(&mut self, n: usize) -> Vec<Piece> {
+        while self.queue.len() < n {
+            let id = self.generator.generate(&mut self.rng);
+            self.queue.push_back(id);
+        }
+        self.queue
+            .iter()
+            .take(n)
+            .map(|id| PIECES[*id].clone())
+            .collect()
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+struct PureRandom;
+
+impl Generator for PureRandom {
+    fn generate(&mut self, rng: &mut ChaCha8Rng) -> usize {
+        rng.gen_range(0..PIECES.len())
+    }
+}
+
+/// Rolls an eighth "reroll" option along with the seven pieces, and rerolls
+/// once if it comes up or the piece repeats the last one.
+#[derive(Default)]
+struct Nes {
+    last: Option<usize>,
+}
+
+impl Generator for Nes {
+    fn generate(&mut self, rng: &mut ChaCha8Rng) -> usize {
+        let mut id = rng.gen_range(0..=PIECES.len());
+        if id == PIECES.len() || Some(id) == self.last {
+            id = rng.gen_range(0..PIECES.len());
+        }
+        self.last = Some(id);
+        id
+    }
+}
+
+/// Rolls up to `rolls` times before accepting a piece from the last four
+/// dealt. The first piece is never an S, Z or O.
+struct Tgm {
+    rolls: usize,
+    history: VecDeque<usize>,
+    first: bool,
+}
+
+impl Tgm {
+    fn new(rolls: usize, history: [Piece; 4]) -> Self {
+        Self {
+            rolls,
+            history: history.iter().map(|p| p.piece_id).collect(),
+            first: true,
+        }
+    }
+}
+
+impl Generator for Tgm {
+    fn generate(&mut self, rng: &mut ChaCha8Rng) -> usize {
+        let id = if self.first {
+            self.first = false;
+            [I, J, L, T].choose(rng).unwrap().piece_id
+        } else {
+            let mut id = rng.gen_range(0..PIECES.len());
+            for _ in 1..self.rolls {
+                if !self.history.contains(&id) {
+                    break;
+                }
+                id = rng.gen_range(0..PIECES.len());
+            }
+            id
+        };
+        self.history.pop_front();
+        self.history.push_back(id);
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deal(randomizer: &mut dyn Randomizer, n: usize) -> Vec<usize> {
+        (0..n).map(|_| randomizer.next_piece().piece_id).collect()
+    }
+
+    fn ids(pieces: &[Piece]) -> Vec<usize> {
+        pieces.iter().map(|p| p.piece_id).collect()
+    }
+
+    #[test]
+    fn same_seed_deals_same_pieces() {
+        for kind in RandomizerKind::ALL {
+            let a = deal(kind.create(42).as_mut(), 100);
+            let b = deal(kind.create(42).as_mut(), 100);
+            let c = deal(kind.create(43).as_mut(), 100);
+            assert_eq!(a, b, "{}", kind.name());
+            assert_ne!(a, c, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn peek_matches_dealt_pieces() {
+        for kind in RandomizerKind::ALL {
+            let mut randomizer = kind.create(7);
+            let mut peeked = Vec::new();
+            let mut dealt = Vec::new();
+            // Preview lengths that don't line up with the bag sizes
+            for n in [1, 5, 3, 16, 2, 9] {
+                peeked.push(ids(&randomizer.peek_n(n)));
+                dealt.push(deal(randomizer.as_mut(), n));
+            }
+            assert_eq!(peeked, dealt, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn peeking_doesnt_change_the_sequence() {
+        for kind in RandomizerKind::ALL {
+            let mut peeking = kind.create(11);
+            let mut dealt = Vec::new();
+            for _ in 0..50 {
+                peeking.peek_n(6);
+                dealt.push(peeking.next_piece().piece_id);
+            }
+            assert_eq!(dealt, deal(kind.create(11).as_mut(), 50), "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn bags_deal_every_piece() {
+        for (kind, copies) in [
+            (RandomizerKind::SevenBag, 1),
+            (RandomizerKind::FourteenBag, 2),
+        ] {
+            let mut randomizer = kind.create(3);
+            let size = PIECES.len() * copies;
+            for _ in 0..5 {
+                let mut bag = deal(randomizer.as_mut(), size);
+                bag.sort();
+                let expected: Vec<_> = (0..PIECES.len())
+                    .flat_map(|id| std::iter::repeat_n(id, copies))
+                    .collect();
+                assert_eq!(bag, expected, "{}", kind.name());
+            }
+        }
+    }
+
+    #[test]
+    fn tgm_never_starts_with_s_z_or_o() {
+        for kind in [RandomizerKind::Tgm, RandomizerKind::Tgm2] {
+            for seed in 0..100 {
+                let first = kind.create(seed).next_piece().piece_id;
+                assert!(![S.piece_id, Z.piece_id, 3].contains(&first));
+            }
+        }
+    }
+}
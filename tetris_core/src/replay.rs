@@ -0,0 +1,169 @@
+use crate::TICK_RATE;
+use crate::game::{Game, Handling, Input};
+use crate::gravity::GravityCurve;
+use crate::mode::GameMode;
+use crate::randomizer::RandomizerKind;
+
+/// Bumped whenever a change to the rules or the format would make older
+/// replays play back differently.
+pub const REPLAY_VERSION: u32 = 8;
+
+/// A recording of a game. Playing the same inputs back through a game made by
+/// [Replay::start] reproduces it exactly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Replay {
+    pub version: u32,
+    pub mode: GameMode,
+    pub gravity: GravityCurve,
+    pub start_level: usize,
+    pub randomizer: RandomizerKind,
+    pub seed: u64,
+    pub handling: Handling,
+    /// Updates recorded
+    ticks: u32,
+    /// The update each time the held buttons changed, and the buttons held from
+    /// then on as [Input::to_bits]
+    inputs: Vec<(u32, u8)>,
+}
+
+impl Replay {
+    /// An empty recording for a new game with the given settings.
+    pub fn new(
+        mode: GameMode,
+        gravity: GravityCurve,
+        start_level: usize,
+        randomizer: RandomizerKind,
+        seed: u64,
+        handling: Handling,
+    ) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            mode,
+            gravity,
+            start_level,
+            randomizer,
+            seed,
+            handling,
+            ticks: 0,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// A new game in the state the recording started from.
+    pub fn start(&self) -> Game {
+        Game::new(
+            self.mode,
+            self.gravity,
+            self.start_level,
+            self.handling,
+            self.randomizer.create(self.seed),
+        )
+    }
+
+    /// Add an update to the end of the recording.
+    pub fn record(&mut self, input: Input) {
+        let bits = input.to_bits();
+        if self.inputs.last().is_none_or(|(_, last)| *last != bits) {
+            self.inputs.push((self.ticks, bits));
+        }
+        self.ticks += 1;
+    }
+
+    /// How many updates were recorded.
+    pub fn len(&self) -> usize {
+        self.ticks as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks == 0
+    }
+
+    /// The buttons held in a recorded update.
+    pub fn frame(&self, frame: usize) -> Option<Input> {
+        if frame >= self.len() {
+            return None;
+        }
+        let i = self.inputs.partition_point(|(f, _)| *f as usize <= frame);
+        Some(match i {
+            0 => Input::default(),
+            _ => Input::from_bits(self.inputs[i - 1].1),
+        })
+    }
+
+    /// Seconds covered by the whole recording.
+    pub fn duration(&self) -> f32 {
+        self.ticks as f32 / TICK_RATE as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::Cheese;
+
+    /// Buttons that change every so often in a way that moves, rotates and
+    /// drops pieces all over the board.
+    fn input(tick: u32) -> Input {
+        let step = tick / 7;
+        let hash = step.wrapping_mul(2_654_435_761) >> 16;
+        Input::from_bits(match step % 5 {
+            4 => 1 << 3,
+            _ => (hash & 0b1111_0111) as u8,
+        })
+    }
+
+    fn record(mode: GameMode, randomizer: RandomizerKind, ticks: u32) -> (Replay, Game) {
+        let mut replay = Replay::new(
+            mode,
+            GravityCurve::Guideline,
+            3,
+            randomizer,
+            1234,
+            Handling::default(),
+        );
+        let mut game = replay.start();
+        for tick in 0..ticks {
+            let input = input(tick);
+            replay.record(input);
+            game.update(input);
+        }
+        (replay, game)
+    }
+
+    #[test]
+    fn playback_reproduces_the_game() {
+        let modes = [
+            GameMode::Marathon,
+            GameMode::Sprint,
+            GameMode::Ultra,
+            GameMode::Cheese(Cheese::STANDARD),
+        ];
+        for mode in modes {
+            for randomizer in RandomizerKind::ALL {
+                let (replay, recorded) = record(mode, randomizer, 3000);
+                assert!(recorded.scoring().score() > 0);
+
+                let mut played = replay.start();
+                for frame in 0..replay.len() {
+                    played.update(replay.frame(frame).unwrap());
+                }
+                assert_eq!(played.board().state, recorded.board().state);
+                assert_eq!(played.scoring().score(), recorded.scoring().score());
+                assert_eq!(played.scoring().lines(), recorded.scoring().lines());
+                assert_eq!(played.is_over(), recorded.is_over());
+                assert_eq!(played.elapsed(), recorded.elapsed());
+            }
+        }
+    }
+
+    #[test]
+    fn frames_return_the_recorded_input() {
+        let (replay, _) = record(GameMode::Marathon, RandomizerKind::SevenBag, 500);
+        assert_eq!(replay.len(), 500);
+        for tick in 0..500 {
+            assert_eq!(replay.frame(tick as usize), Some(input(tick)));
+        }
+        assert_eq!(replay.frame(500), None);
+    }
+}
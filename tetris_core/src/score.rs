@@ -0,0 +1,276 @@
+/// T-spin classification for a locked piece
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Spin {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+/// The highest level a game can be started from
+pub const MAX_START_LEVEL: usize = 29;
+
+#[derive(Default, Clone)]
+pub struct Scoring {
+    /// The level the game started on
+    start_level: usize,
+    score: usize,
+    lines: usize,
+    /// Consecutive placements that cleared lines, starting from 0
+    combo: Option<usize>,
+    /// Consecutive difficult clears (tetrises and T-spins), starting from 0
+    back_to_back: Option<usize>,
+}
+
+impl Scoring {
+    pub fn new(start_level: usize) -> Self {
+        Self {
+            start_level: start_level.min(MAX_START_LEVEL),
+            ..Default::default()
+        }
+    }
+
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn start_level(&self) -> usize {
+        self.start_level
+    }
+
+    /// The level goes up every 10 lines, never dropping below the starting
+    /// level. As on the NES, a higher start delays the first level up until
+    /// `min(start * 10 + 10, max(100, start * 10 - 50))` lines are cleared.
+    pub fn level(&self) -> usize {
+        let start = self.start_level;
+        let first = (start * 10 + 10).min((start * 10).saturating_sub(50).max(100));
+        if self.lines < first {
+            start
+        } else {
+            start + 1 + (self.lines - first) / 10
+        }
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// How many placements in a row have cleared lines after the first.
+    pub fn combo(&self) -> usize {
+        self.combo.unwrap_or(0)
+    }
+
+    /// How many difficult clears in a row have followed the first.
+    pub fn back_to_back(&self) -> usize {
+        self.back_to_back.unwrap_or(0)
+    }
+
+    /// Score a placed piece. T-spins are scored even if they didn't clear any lines.
+    ///
+    /// A placement that doesn't clear lines breaks the combo, and a line clear
+    /// that isn't a tetris or T-spin breaks back-to-back.
+    pub fn line_clears(&mut self, lines: usize, spin: Spin) {
+        let mut value = self.clear_value(lines, spin);
+
+        if lines == 0 {
+            self.combo = None;
+        } else {
+            if lines == 4 || spin != Spin::None {
+                if self.back_to_back.is_some() {
+                    value = value * 3 / 2;
+                }
+                self.back_to_back = Some(self.back_to_back.map_or(0, |b| b + 1));
+            } else {
+                self.back_to_back = None;
+            }
+
+            let combo = self.combo.map_or(0, |c| c + 1);
+            self.combo = Some(combo);
+            value += 50 * combo * (self.level() + 1);
+        }
+
+        self.score += value;
+        self.lines += lines;
+    }
+
+    /// Bonus for clearing every block from the board. Should be called after
+    /// the clear itself has been scored with [Scoring::line_clears].
+    pub fn perfect_clear(&mut self, lines: usize) {
+        let bonus = match lines {
+            1 => 800,
+            2 => 1200,
+            3 => 1800,
+            _ if self.back_to_back() > 0 => 3200,
+            _ => 2000,
+        };
+        self.score += bonus * (self.level() + 1);
+    }
+
+    fn clear_value(&self, lines: usize, spin: Spin) -> usize {
+        let score = match (spin, lines) {
+            (Spin::None, 0) => 0,
+            (Spin::None, 2) => 300,
+            (Spin::None, 3) => 500,
+            (Spin::None, 4) => 800,
+            (Spin::None, _) => 100,
+            (Spin::Mini, 0) => 100,
+            (Spin::Mini, 1) => 200,
+            (Spin::Mini, _) => 400,
+            (Spin::Full, 0) => 400,
+            (Spin::Full, 1) => 800,
+            (Spin::Full, 2) => 1200,
+            (Spin::Full, _) => 1600,
+        };
+        score * (self.level() + 1)
+    }
+
+    pub fn soft_drop(&mut self, amount: usize) {
+        self.score += amount;
+    }
+
+    pub fn hard_drop(&mut self, amount: usize) {
+        self.score += amount * 2;
+    }
+}
+
+/// The display name of a clear, ie "T-SPIN DOUBLE". Returns `None` for a
+/// placement that didn't clear any lines or spin.
+pub fn clear_name(lines: usize, spin: Spin) -> Option<String> {
+    let spin = match spin {
+        Spin::None => None,
+        Spin::Mini => Some("MINI T-SPIN"),
+        Spin::Full => Some("T-SPIN"),
+    };
+    let lines = match lines {
+        0 => None,
+        1 => Some("SINGLE"),
+        2 => Some("DOUBLE"),
+        3 => Some("TRIPLE"),
+        _ => Some("TETRIS"),
+    };
+    match (spin, lines) {
+        (Some(spin), Some(lines)) => Some(format!("{spin} {lines}")),
+        (Some(name), None) | (None, Some(name)) => Some(name.to_string()),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scoring after clearing singles until `lines` lines are cleared.
+    fn with_lines(start_level: usize, lines: usize) -> Scoring {
+        let mut scoring = Scoring::new(start_level);
+        for _ in 0..lines {
+            scoring.line_clears(1, Spin::None);
+        }
+        scoring
+    }
+
+    #[test]
+    fn level_goes_up_every_10_lines() {
+        assert_eq!(with_lines(0, 9).level(), 0);
+        assert_eq!(with_lines(0, 10).level(), 1);
+        assert_eq!(with_lines(0, 29).level(), 2);
+        assert_eq!(with_lines(5, 59).level(), 5);
+        assert_eq!(with_lines(5, 60).level(), 6);
+    }
+
+    #[test]
+    fn high_start_level_delays_first_level_up() {
+        assert_eq!(with_lines(9, 99).level(), 9);
+        assert_eq!(with_lines(9, 100).level(), 10);
+        assert_eq!(with_lines(15, 99).level(), 15);
+        assert_eq!(with_lines(15, 100).level(), 16);
+        assert_eq!(with_lines(19, 139).level(), 19);
+        assert_eq!(with_lines(19, 140).level(), 20);
+        assert_eq!(with_lines(19, 150).level(), 21);
+    }
+
+    #[test]
+    fn combo_and_back_to_back() {
+        let mut scoring = Scoring::new(0);
+        scoring.line_clears(4, Spin::None);
+        assert_eq!(scoring.score(), 800);
+        assert_eq!(scoring.combo(), 0);
+        assert_eq!(scoring.back_to_back(), 0);
+
+        // 1.5x for back-to-back, plus 50 for the combo
+        scoring.line_clears(4, Spin::None);
+        assert_eq!(scoring.score(), 800 + 1200 + 50);
+        assert_eq!(scoring.combo(), 1);
+        assert_eq!(scoring.back_to_back(), 1);
+
+        // A single breaks back-to-back but keeps the combo going
+        scoring.line_clears(1, Spin::None);
+        assert_eq!(scoring.score(), 2050 + 100 + 100);
+        assert_eq!(scoring.combo(), 2);
+        assert_eq!(scoring.back_to_back(), 0);
+
+        scoring.line_clears(0, Spin::None);
+        assert_eq!(scoring.score(), 2250);
+        assert_eq!(scoring.combo(), 0);
+    }
+
+    #[test]
+    fn t_spins_keep_back_to_back() {
+        let mut scoring = Scoring::new(0);
+        scoring.line_clears(4, Spin::None);
+        scoring.line_clears(0, Spin::Full);
+        assert_eq!(scoring.score(), 800 + 400);
+
+        scoring.line_clears(2, Spin::Full);
+        assert_eq!(scoring.score(), 1200 + 1800);
+        assert_eq!(scoring.back_to_back(), 1);
+
+        scoring.line_clears(1, Spin::Mini);
+        assert_eq!(scoring.score(), 3000 + 300 + 50);
+        assert_eq!(scoring.back_to_back(), 2);
+    }
+
+    #[test]
+    fn scores_scale_with_level() {
+        let mut scoring = Scoring::new(4);
+        scoring.line_clears(2, Spin::None);
+        assert_eq!(scoring.score(), 300 * 5);
+    }
+
+    #[test]
+    fn perfect_clear_bonus() {
+        let mut scoring = Scoring::new(0);
+        scoring.line_clears(2, Spin::None);
+        scoring.perfect_clear(2);
+        assert_eq!(scoring.score(), 300 + 1200);
+
+        let mut scoring = Scoring::new(0);
+        scoring.line_clears(4, Spin::None);
+        scoring.perfect_clear(4);
+        assert_eq!(scoring.score(), 800 + 2000);
+
+        // A back-to-back tetris perfect clear is worth more
+        scoring.line_clears(4, Spin::None);
+        scoring.perfect_clear(4);
+        assert_eq!(scoring.score(), 2800 + 1250 + 3200);
+    }
+
+    #[test]
+    fn drops() {
+        let mut scoring = Scoring::new(0);
+        scoring.soft_drop(3);
+        scoring.hard_drop(5);
+        assert_eq!(scoring.score(), 3 + 10);
+    }
+
+    #[test]
+    fn clear_names() {
+        assert_eq!(clear_name(0, Spin::None), None);
+        assert_eq!(clear_name(4, Spin::None).as_deref(), Some("TETRIS"));
+        assert_eq!(clear_name(0, Spin::Full).as_deref(), Some("T-SPIN"));
+        assert_eq!(clear_name(2, Spin::Full).as_deref(), Some("T-SPIN DOUBLE"));
+        assert_eq!(
+            clear_name(1, Spin::Mini).as_deref(),
+            Some("MINI T-SPIN SINGLE")
+        );
+    }
+}
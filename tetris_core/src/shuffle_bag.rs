@@ -0,0 +1,97 @@
+use rand::{SeedableRng, seq::SliceRandom};
+use rand_chacha::ChaCha8Rng;
+
+use crate::piece::{PIECES, Piece};
+use crate::randomizer::Randomizer;
+
+/// A bag randomizer. Pieces are dealt from a shuffled bag holding a number of
+/// copies of all seven pieces, so the same seed always deals the same sequence.
+#[derive(Clone)]
+pub struct ShuffleBag {
+    pieces: Vec<Piece>,
+    copies: usize,
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl Default for ShuffleBag {
+    fn default() -> Self {
+        Self::with_seed(rand::random())
+    }
+}
+
+impl ShuffleBag {
+    /// A standard 7-bag.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_copies(1, seed)
+    }
+
+    /// A bag holding `copies` of each piece, ie 2 for a 14-bag.
+    pub fn with_copies(copies: usize, seed: u64) -> Self {
+        let mut bag = Self {
+            pieces: Vec::new(),
+            copies,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        };
+        bag.refill();
+        bag
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn get_piece(&mut self) -> Piece {
+        if self.pieces.is_empty() {
+            self.refill();
+        }
+
+        let piece = self.pieces.remove(self.pieces.len() - 1);
+
+        if self.pieces.is_empty() {
+            self.refill();
+        }
+
+        piece
+    }
+
+    pub fn peek(&self) -> &Piece {
+        self.pieces.last().unwrap()
+    }
+
+    fn refill(&mut self) {
+        let bag = self.shuffled_bag();
+        self.pieces.extend(bag);
+    }
+
+    fn shuffled_bag(&mut self) -> Vec<Piece> {
+        let mut bag = Vec::with_capacity(PIECES.len() * self.copies);
+        for _ in 0..self.copies {
+            bag.extend(PIECES);
+        }
+        bag.shuffle(&mut self.rng);
+        bag
+    }
+}
+
+impl Randomizer for ShuffleBag {
+    fn next_piece(&mut self) -> Piece {
+        self.get_piece()
+    }
+
+    /// Bags are shuffled ahead of time when the preview reaches past the end of
+    /// the current one. Pieces are dealt from the back so upcoming bags go in
+    /// the front.
+    fn peek_n(&mut self, n: usize) -> Vec<Piece> {
+        while self.pieces.len() < n {
+            let bag = self.shuffled_bag();
+            self.pieces.splice(0..0, bag);
+        }
+        self.pieces.iter().rev().take(n).cloned().collect()
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+}